@@ -0,0 +1,262 @@
+use crate::{day11, day20, day25};
+use std::io;
+use std::io::Write;
+use std::sync::mpsc;
+use std::sync::mpsc::RecvTimeoutError;
+use std::thread;
+use std::time::Duration;
+
+// a step by step simulation that can be replayed in the terminal
+pub trait Simulation {
+    // current state, with ANSI colours
+    fn render(&self) -> String;
+
+    // move on one step, returns false once there is nothing more to show
+    fn advance(&mut self) -> bool;
+}
+
+// wrap text in an ANSI colour escape sequence
+pub fn paint(s: &str, colour: &str) -> String {
+    format!("\x1b[{}m{}\x1b[0m", colour, s)
+}
+
+const USAGE: &str = "\
+usage: adventofcode-2021 animate <day> [--delay MS] [--from STEP] [--to STEP] [--paused]
+days: 11, 20, 25
+controls (type then press enter):
+  <enter>  pause, or show the next step when paused
+  c        continue playing
+  q        quit";
+
+#[derive(Debug, PartialEq)]
+struct Options {
+    day: i32,
+    delay: Duration,
+    from: usize,       // first step to show, earlier steps run without display
+    to: Option<usize>, // last step to show, otherwise until the simulation ends
+    paused: bool,      // wait for enter before each step
+}
+
+impl Options {
+    fn parse(args: &[String]) -> Result<Options, String> {
+        let mut options = Options {
+            day: 0,
+            delay: Duration::from_millis(100),
+            from: 0,
+            to: None,
+            paused: false,
+        };
+        let mut args = args.iter();
+        let number = |flag: &str, value: Option<&String>| -> Result<u64, String> {
+            value
+                .ok_or(format!("{} needs a value", flag))?
+                .parse()
+                .map_err(|_| format!("{} needs a number", flag))
+        };
+        while let Some(arg) = args.next() {
+            match arg.as_str() {
+                "--delay" => options.delay = Duration::from_millis(number(arg, args.next())?),
+                "--from" => options.from = number(arg, args.next())? as usize,
+                "--to" => options.to = Some(number(arg, args.next())? as usize),
+                "--paused" => options.paused = true,
+                _ => {
+                    options.day = arg
+                        .parse()
+                        .map_err(|_| format!("unknown argument {}", arg))?
+                }
+            }
+        }
+        if options.day == 0 {
+            return Err("no day given".to_string());
+        }
+        if options.to.is_some_and(|to| to < options.from) {
+            return Err("--to is before --from".to_string());
+        }
+        Ok(options)
+    }
+}
+
+fn simulation(day: i32, input: &str) -> Result<Box<dyn Simulation>, String> {
+    match day {
+        11 => Ok(day11::simulation(input)),
+        20 => Ok(day20::simulation(input)),
+        25 => Ok(day25::simulation(input)),
+        _ => Err(format!("day {} has no animation", day)),
+    }
+}
+
+enum Command {
+    Pause,
+    Continue,
+    Quit,
+}
+
+// read commands from stdin on their own thread so frames keep coming
+fn read_commands() -> mpsc::Receiver<Command> {
+    let (sender, receiver) = mpsc::channel();
+    thread::spawn(move || {
+        for line in io::stdin().lines() {
+            let command = match line.as_deref().map(str::trim) {
+                Ok("") => Command::Pause,
+                Ok("c") => Command::Continue,
+                Ok("q") | Err(_) => Command::Quit,
+                Ok(_) => continue,
+            };
+            if sender.send(command).is_err() {
+                break;
+            }
+        }
+    });
+    receiver
+}
+
+// render every frame from step `from` to `to` inclusive, stopping early if
+// the simulation ends.  show returns false to stop.
+fn play(
+    sim: &mut dyn Simulation,
+    from: usize,
+    to: Option<usize>,
+    mut show: impl FnMut(usize, String) -> bool,
+) {
+    let mut step = 0;
+    while step < from {
+        if !sim.advance() {
+            return;
+        }
+        step += 1;
+    }
+    loop {
+        if !show(step, sim.render()) || to.is_some_and(|to| step >= to) || !sim.advance() {
+            return;
+        }
+        step += 1;
+    }
+}
+
+pub fn run(args: &[String]) {
+    if let Err(e) = animate(args) {
+        println!("{}\n{}", e, USAGE);
+    }
+}
+
+fn animate(args: &[String]) -> Result<(), String> {
+    let options = Options::parse(args)?;
+    let filename = format!("input/day{}.txt", options.day);
+    let input = crate::read_file(&filename).map_err(|e| format!("{}: {}", filename, e))?;
+    let mut sim = simulation(options.day, &input)?;
+
+    let commands = read_commands();
+    let mut paused = options.paused;
+    let mut stdout = io::stdout();
+    play(sim.as_mut(), options.from, options.to, |step, frame| {
+        // clear the screen and draw from the top left corner
+        print!(
+            "\x1b[2J\x1b[H{}\n{}",
+            paint(&format!("step {}", step), "1"),
+            frame
+        );
+        stdout.flush().unwrap();
+        loop {
+            let command = if paused {
+                match commands.recv() {
+                    Ok(command) => command,
+                    Err(_) => Command::Continue, // stdin closed, nobody to unpause
+                }
+            } else {
+                match commands.recv_timeout(options.delay) {
+                    Ok(command) => command,
+                    Err(RecvTimeoutError::Timeout) => return true,
+                    Err(RecvTimeoutError::Disconnected) => {
+                        thread::sleep(options.delay);
+                        return true;
+                    }
+                }
+            };
+            match command {
+                Command::Pause if paused => return true, // next step
+                Command::Pause => paused = true,
+                Command::Continue if paused => {
+                    paused = false;
+                    return true;
+                }
+                Command::Continue => (),
+                Command::Quit => return false,
+            }
+        }
+    });
+    Ok(())
+}
+
+#[test]
+fn test() {
+    let args = |s: &str| -> Vec<String> { s.split_whitespace().map(String::from).collect() };
+    assert_eq!(
+        Ok(Options {
+            day: 25,
+            delay: Duration::from_millis(20),
+            from: 3,
+            to: Some(5),
+            paused: true,
+        }),
+        Options::parse(&args("25 --delay 20 --from 3 --to 5 --paused"))
+    );
+    assert_eq!(
+        Err("no day given".to_string()),
+        Options::parse(&args("--paused"))
+    );
+    assert_eq!(
+        Err("--delay needs a number".to_string()),
+        Options::parse(&args("11 --delay x"))
+    );
+    assert_eq!(
+        Err("--to is before --from".to_string()),
+        Options::parse(&args("11 --from 4 --to 2"))
+    );
+    assert!(simulation(12, "").is_err());
+
+    // nothing moves on step 58, so the last new frame is step 57
+    let input = "\
+v...>>.vv>
+.vv>>.vv..
+>>.>v>...v
+>>v>>.>.v.
+v>v.vv.v..
+>.>>..v...
+.vv..>.>v.
+v.v..>>v.v
+....v..v.>
+";
+    let mut steps = vec![];
+    play(day25::simulation(input).as_mut(), 0, None, |step, _| {
+        steps.push(step);
+        true
+    });
+    assert_eq!((0..=57).collect::<Vec<_>>(), steps);
+
+    let mut frames = vec![];
+    play(
+        day25::simulation(input).as_mut(),
+        2,
+        Some(4),
+        |step, frame| {
+            frames.push((step, frame));
+            true
+        },
+    );
+    assert_eq!(
+        vec![2, 3, 4],
+        frames.iter().map(|f| f.0).collect::<Vec<_>>()
+    );
+    assert!(frames[0].1.contains(&paint(">", "32")));
+
+    // every octopus flashes together on step 6, unless stopped sooner
+    let octopuses = "11111\n19991\n19191\n19991\n11111\n";
+    for (limit, expected) in [(3, 3), (10, 7)] {
+        let mut count = 0;
+        play(day11::simulation(octopuses).as_mut(), 0, None, |_, _| {
+            count += 1;
+            count < limit
+        });
+        assert_eq!(expected, count);
+    }
+}
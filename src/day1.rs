@@ -43,7 +43,7 @@ fn count_sliding_increases(input: &[i32]) -> i32 {
 fn test_count_increases() {
     assert_eq!(
         7,
-        count_increases(&vec![199, 200, 208, 210, 200, 207, 240, 269, 260, 263])
+        count_increases(&[199, 200, 208, 210, 200, 207, 240, 269, 260, 263])
     )
 }
 
@@ -51,6 +51,6 @@ fn test_count_increases() {
 fn test_count_sliding_increases() {
     assert_eq!(
         5,
-        count_sliding_increases(&vec![199, 200, 208, 210, 200, 207, 240, 269, 260, 263])
+        count_sliding_increases(&[199, 200, 208, 210, 200, 207, 240, 269, 260, 263])
     )
 }
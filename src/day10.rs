@@ -1,107 +1,239 @@
 use crate::Part;
+use std::fmt;
 
 pub fn run(input: &str, part: Part) -> String {
     let lines = parse_input(input);
-    format!(
-        "{}",
-        match part {
-            Part::One => part1(&lines),
-            Part::Two => part2(&lines),
+    let delimiters = Delimiters::default();
+    let result = match part {
+        Part::One => part1(&lines, &delimiters).map(Some),
+        Part::Two => part2(&lines, &delimiters),
+    };
+    match result {
+        Ok(Some(score)) if crate::verbose() => {
+            format!("{}\n{}", score, report(&lines, &delimiters))
         }
-    )
+        Ok(Some(score)) => format!("{}", score),
+        Ok(None) => "no incomplete lines".to_string(),
+        Err(e) => e.to_string(),
+    }
+}
+
+// an opening and closing character, with the score for finding the closer
+// where it doesn't belong and for adding it to complete a line
+#[derive(Clone, Copy, Debug)]
+struct Delimiter {
+    open: char,
+    close: char,
+    illegal_score: usize,
+    completion_score: usize,
+}
+
+#[derive(Debug)]
+struct Delimiters {
+    pairs: Vec<Delimiter>,
+}
+
+impl Default for Delimiters {
+    fn default() -> Self {
+        Delimiters::new(&[
+            ('(', ')', 3, 1),
+            ('[', ']', 57, 2),
+            ('{', '}', 1197, 3),
+            ('<', '>', 25137, 4),
+        ])
+        .unwrap()
+    }
+}
+
+// a pair whose characters are the same, or already used by another pair
+#[derive(Debug, PartialEq)]
+struct DelimiterError {
+    open: char,
+    close: char,
 }
 
-fn illegal_char_score(c: char) -> usize {
-    match c {
-        ')' => 3,
-        ']' => 57,
-        '}' => 1197,
-        '>' => 25137,
-        _ => panic!("unexpected token {}", c),
+impl fmt::Display for DelimiterError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "delimiters {}{} overlap with another pair",
+            self.open, self.close
+        )
+    }
+}
+
+impl Delimiters {
+    // pairs of (open, close, illegal score, completion score)
+    fn new(pairs: &[(char, char, usize, usize)]) -> Result<Delimiters, DelimiterError> {
+        let mut seen = vec![];
+        for &(open, close, _, _) in pairs {
+            if open == close || seen.contains(&open) || seen.contains(&close) {
+                return Err(DelimiterError { open, close });
+            }
+            seen.extend([open, close]);
+        }
+        Ok(Delimiters {
+            pairs: pairs
+                .iter()
+                .map(
+                    |&(open, close, illegal_score, completion_score)| Delimiter {
+                        open,
+                        close,
+                        illegal_score,
+                        completion_score,
+                    },
+                )
+                .collect(),
+        })
+    }
+
+    fn opened_by(&self, c: char) -> Option<&Delimiter> {
+        self.pairs.iter().find(|d| d.open == c)
+    }
+
+    fn closed_by(&self, c: char) -> Option<&Delimiter> {
+        self.pairs.iter().find(|d| d.close == c)
     }
 }
 
-fn matching_char_for(c: char) -> char {
-    match c {
-        ')' => '(',
-        ']' => '[',
-        '}' => '{',
-        '>' => '<',
-        _ => panic!("unexpected token {}", c),
+#[derive(Debug, PartialEq)]
+enum Diagnostic {
+    // first closer that doesn't match, expected is None if nothing was open
+    Corrupted {
+        column: usize,
+        expected: Option<char>,
+        found: char,
+        score: usize,
+    },
+    // closers needed to finish the line, empty if it is already complete
+    Incomplete {
+        completion: String,
+        score: usize,
+    },
+}
+
+impl fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Diagnostic::Corrupted {
+                column,
+                expected: Some(expected),
+                found,
+                ..
+            } => write!(
+                f,
+                "column {}: expected {}, found {}",
+                column, expected, found
+            ),
+            Diagnostic::Corrupted { column, found, .. } => {
+                write!(f, "column {}: found {} with nothing open", column, found)
+            }
+            Diagnostic::Incomplete { completion, .. } if completion.is_empty() => {
+                write!(f, "complete")
+            }
+            Diagnostic::Incomplete { completion, .. } => {
+                write!(f, "incomplete, complete by adding {}", completion)
+            }
+        }
     }
 }
 
-fn matching_char_score(c: char) -> usize {
-    match c {
-        '(' => 1,
-        '[' => 2,
-        '{' => 3,
-        '<' => 4,
-        _ => panic!("unexpected token {}", c),
+#[derive(Debug, PartialEq)]
+struct SyntaxError {
+    line: usize,
+    column: usize,
+    found: char,
+}
+
+impl fmt::Display for SyntaxError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "line {}, column {}: unknown character {:?}",
+            self.line, self.column, self.found
+        )
     }
 }
 
-enum Result {
-    Corrupted(usize),
-    Incomplete(Vec<char>),
-}
-
-// if line is corrupt, return Corrupted<score>
-// if line is not corrupt, return Incomplete<stack> of the leftover stack
-fn parse(line: &str) -> Result {
-    let mut stack: Vec<char> = vec![];
-    for c in line.chars() {
-        match c {
-            '(' | '[' | '{' | '<' => stack.push(c),
-            ')' | ']' | '}' | '>' => {
-                if let Some(o) = stack.pop() {
-                    if o != matching_char_for(c) {
-                        return Result::Corrupted(illegal_char_score(c));
-                    }
-                } else {
-                    return Result::Corrupted(illegal_char_score(c));
+// check a line, columns are counted from 1. an unknown character is an error
+// with the line number left as 0 for the caller to fill in.
+fn parse(line: &str, delimiters: &Delimiters) -> Result<Diagnostic, SyntaxError> {
+    let mut stack: Vec<&Delimiter> = vec![];
+    for (i, c) in line.chars().enumerate() {
+        if let Some(d) = delimiters.opened_by(c) {
+            stack.push(d);
+        } else if let Some(d) = delimiters.closed_by(c) {
+            match stack.pop() {
+                Some(open) if open.close == c => (),
+                open => {
+                    return Ok(Diagnostic::Corrupted {
+                        column: i + 1,
+                        expected: open.map(|o| o.close),
+                        found: c,
+                        score: d.illegal_score,
+                    })
                 }
             }
-            _ => panic!("unexpected token: {}", c),
+        } else {
+            return Err(SyntaxError {
+                line: 0,
+                column: i + 1,
+                found: c,
+            });
         }
     }
-    Result::Incomplete(stack)
+    Ok(Diagnostic::Incomplete {
+        completion: stack.iter().rev().map(|d| d.close).collect(),
+        score: stack
+            .iter()
+            .rev()
+            .fold(0, |acc, d| acc * 5 + d.completion_score),
+    })
 }
 
-fn part1(lines: &[String]) -> usize {
+fn diagnose(lines: &[String], delimiters: &Delimiters) -> Result<Vec<Diagnostic>, SyntaxError> {
     lines
         .iter()
-        .filter_map(|line| {
-            if let Result::Corrupted(score) = parse(line) {
-                Some(score)
-            } else {
-                None
-            }
+        .enumerate()
+        .map(|(i, line)| parse(line, delimiters).map_err(|e| SyntaxError { line: i + 1, ..e }))
+        .collect()
+}
+
+// one line per input line describing what is wrong with it
+fn report(lines: &[String], delimiters: &Delimiters) -> String {
+    lines
+        .iter()
+        .enumerate()
+        .map(|(i, line)| match parse(line, delimiters) {
+            Ok(diagnostic) => format!("line {}: {}", i + 1, diagnostic),
+            Err(e) => SyntaxError { line: i + 1, ..e }.to_string(),
         })
-        .sum()
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
-fn completion_score(stack: &[char]) -> usize {
-    stack
+fn part1(lines: &[String], delimiters: &Delimiters) -> Result<usize, SyntaxError> {
+    Ok(diagnose(lines, delimiters)?
         .iter()
-        .rev()
-        .fold(0, |acc, c| acc * 5 + matching_char_score(*c))
+        .filter_map(|diagnostic| match diagnostic {
+            Diagnostic::Corrupted { score, .. } => Some(score),
+            _ => None,
+        })
+        .sum())
 }
 
-fn part2(lines: &[String]) -> usize {
-    let mut scores: Vec<usize> = lines
+// the middle score of the incomplete lines, None if there aren't any.  lines
+// that are already complete don't need completing, so they don't count.
+fn part2(lines: &[String], delimiters: &Delimiters) -> Result<Option<usize>, SyntaxError> {
+    let mut scores: Vec<usize> = diagnose(lines, delimiters)?
         .iter()
-        .filter_map(|line| {
-            if let Result::Incomplete(stack) = parse(line) {
-                Some(stack)
-            } else {
-                None
-            }
+        .filter_map(|diagnostic| match diagnostic {
+            Diagnostic::Incomplete { completion, score } if !completion.is_empty() => Some(*score),
+            _ => None,
         })
-        .map(|stack| completion_score(&stack))
         .collect();
     scores.sort_unstable();
-    scores[scores.len() / 2]
+    Ok(scores.get(scores.len() / 2).copied())
 }
 
 fn parse_input(input: &str) -> Vec<String> {
@@ -123,7 +255,64 @@ fn test() {
 <{([{{}}[<[[[<>{}]]]>[]]
 ";
     let lines = parse_input(test_input);
+    let delimiters = Delimiters::default();
     assert_eq!(10, lines.len());
-    assert_eq!(26397, part1(&lines));
-    assert_eq!(288957, part2(&lines));
+    assert_eq!(Ok(26397), part1(&lines, &delimiters));
+    assert_eq!(Ok(Some(288957)), part2(&lines, &delimiters));
+
+    assert_eq!(
+        Ok(Diagnostic::Corrupted {
+            column: 13,
+            expected: Some(']'),
+            found: '}',
+            score: 1197
+        }),
+        parse(&lines[2], &delimiters)
+    );
+    assert_eq!(
+        Ok(Diagnostic::Incomplete {
+            completion: "}}]])})]".to_string(),
+            score: 288957
+        }),
+        parse(&lines[0], &delimiters)
+    );
+    assert_eq!(
+        "line 3: column 13: expected ], found }",
+        report(&lines, &delimiters).lines().nth(2).unwrap()
+    );
+    assert_eq!(
+        "column 1: found ) with nothing open",
+        parse(")", &delimiters).unwrap().to_string()
+    );
+
+    let lines = parse_input("()\n(x)\n");
+    assert_eq!(
+        Err(SyntaxError {
+            line: 2,
+            column: 2,
+            found: 'x'
+        }),
+        part1(&lines, &delimiters)
+    );
+
+    // a different set of delimiters, where < and > are just unknown
+    let delimiters = Delimiters::new(&[('(', ')', 1, 1), ('/', '\\', 10, 2)]).unwrap();
+    let lines = parse_input("(/\\)\n(/)\n((/\n");
+    assert_eq!(Ok(1), part1(&lines, &delimiters));
+    assert_eq!(Ok(Some(56)), part2(&lines, &delimiters)); // the complete line is left out
+    assert_eq!(
+        "line 3: incomplete, complete by adding \\))",
+        report(&lines, &delimiters).lines().nth(2).unwrap()
+    );
+    assert!(part1(&parse_input("<>"), &delimiters).is_err());
+    // nothing to complete when every line is corrupted or already complete
+    assert_eq!(Ok(None), part2(&parse_input("(/)\n()\n"), &delimiters));
+    assert_eq!(
+        DelimiterError {
+            open: '/',
+            close: '>'
+        },
+        Delimiters::new(&[('<', '>', 1, 1), ('/', '>', 2, 2)]).unwrap_err()
+    );
+    assert!(Delimiters::new(&[('|', '|', 1, 1)]).is_err());
 }
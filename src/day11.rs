@@ -1,40 +1,137 @@
+use crate::animate::{paint, Simulation};
 use crate::Part;
+use std::collections::HashMap;
 use std::fmt;
 
 pub fn run(input: &str, part: Part) -> String {
-    let mut grid = Grid::new(input);
-    for _ in 0..100 {
-        grid = grid.step();
-    }
-    format!(
-        "{}",
-        match part {
-            Part::One => grid.total_flashes,
-            Part::Two => {
-                while grid.flashes != grid.rows * grid.cols {
-                    grid = grid.step();
-                }
-                grid.total_steps
+    let grid = Grid::new(input);
+    match part {
+        Part::One => format!("{}", grid.flash_series(100).iter().sum::<usize>()),
+        Part::Two => match grid.first_sync() {
+            Ok(step) if crate::verbose() => {
+                let cycle = match grid.find_cycle() {
+                    Some(cycle) => cycle.to_string(),
+                    None => "no repeat found".to_string(),
+                };
+                // the same octopuses, only passing energy to the 4 orthogonal neighbours
+                let rules = Rules {
+                    neighbourhood: Neighbourhood::VonNeumann,
+                    ..Default::default()
+                };
+                let von_neumann = match Grid::with_rules(input, rules).first_sync() {
+                    Ok(step) => format!("synchronises on step {}", step),
+                    Err(e) => e.to_string(),
+                };
+                format!(
+                    "{}\nthen {}\nwith 4 neighbours: {}",
+                    step, cycle, von_neumann
+                )
             }
+            Ok(step) => format!("{}", step),
+            Err(e) => e.to_string(),
+        },
+    }
+}
+
+pub fn simulation(input: &str) -> Box<dyn Simulation> {
+    Box::new(Grid::new(input))
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Neighbourhood {
+    Moore,      // all 8 surrounding octopuses
+    VonNeumann, // only the 4 orthogonal ones
+}
+
+impl Neighbourhood {
+    fn offsets(&self) -> &'static [(isize, isize)] {
+        match self {
+            Neighbourhood::Moore => &[
+                (-1, -1),
+                (-1, 0),
+                (-1, 1),
+                (0, -1),
+                (0, 1),
+                (1, -1),
+                (1, 0),
+                (1, 1),
+            ],
+            Neighbourhood::VonNeumann => &[(-1, 0), (0, -1), (0, 1), (1, 0)],
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug)]
+struct Rules {
+    threshold: u8, // an octopus flashes when its energy goes above this
+    neighbourhood: Neighbourhood,
+    step_limit: usize, // most steps to try when searching for synchronisation
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Rules {
+            threshold: 9,
+            neighbourhood: Neighbourhood::Moore,
+            step_limit: 100_000,
+        }
+    }
+}
+
+// the grid first repeats the state it had after `start` steps, every `period`
+// steps after that
+#[derive(Debug, PartialEq)]
+struct Cycle {
+    start: usize,
+    period: usize,
+}
+
+impl fmt::Display for Cycle {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "repeats every {} steps from step {}",
+            self.period, self.start
+        )
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum SyncError {
+    Never(Cycle), // states repeat without ever all flashing together
+    StepLimit(usize),
+}
+
+impl fmt::Display for SyncError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SyncError::Never(cycle) => write!(f, "never synchronises, {}", cycle),
+            SyncError::StepLimit(limit) => write!(f, "not synchronised after {} steps", limit),
         }
-    )
+    }
 }
 
 #[derive(Clone)]
 struct Grid {
-    state: Vec<Vec<u8>>,
+    // wider than the threshold, so energy can always go one above it
+    state: Vec<Vec<u16>>,
     rows: usize,
     cols: usize,
     flashes: usize,
     total_flashes: usize,
     total_steps: usize,
+    rules: Rules,
 }
 
 impl Grid {
     fn new(input: &str) -> Grid {
-        let state: Vec<Vec<u8>> = input
+        Grid::with_rules(input, Rules::default())
+    }
+
+    fn with_rules(input: &str, rules: Rules) -> Grid {
+        let state: Vec<Vec<u16>> = input
             .lines()
-            .map(|line| line.chars().map(|c| c as u8 - 48).collect())
+            .map(|line| line.chars().map(|c| c as u16 - 48).collect())
             .collect();
         let rows = state.len();
         let cols = state[0].len();
@@ -45,7 +142,55 @@ impl Grid {
             flashes: 0,
             total_flashes: 0,
             total_steps: 0,
+            rules,
+        }
+    }
+
+    // number of flashes in each of the next steps
+    fn flash_series(&self, steps: usize) -> Vec<usize> {
+        let mut grid = self.clone();
+        (0..steps)
+            .map(|_| {
+                grid = grid.step();
+                grid.flashes
+            })
+            .collect()
+    }
+
+    // step until the same state comes round again
+    fn find_cycle(&self) -> Option<Cycle> {
+        let mut grid = self.clone();
+        let mut seen = HashMap::new();
+        for step in 0..=self.rules.step_limit {
+            if let Some(start) = seen.insert(grid.state.clone(), step) {
+                return Some(Cycle {
+                    start,
+                    period: step - start,
+                });
+            }
+            grid = grid.step();
+        }
+        None
+    }
+
+    // the first step where every octopus flashes at once.  a repeated state
+    // before then means the grid is in a loop that never synchronises.
+    fn first_sync(&self) -> Result<usize, SyncError> {
+        let mut grid = self.clone();
+        let mut seen = HashMap::new();
+        for step in 0..self.rules.step_limit {
+            if let Some(start) = seen.insert(grid.state.clone(), step) {
+                return Err(SyncError::Never(Cycle {
+                    start,
+                    period: step - start,
+                }));
+            }
+            grid = grid.step();
+            if grid.flashes == grid.rows * grid.cols {
+                return Ok(step + 1);
+            }
         }
+        Err(SyncError::StepLimit(self.rules.step_limit))
     }
 
     fn step(&self) -> Grid {
@@ -55,29 +200,25 @@ impl Grid {
         let mut queue: Vec<(usize, usize)> = vec![];
 
         // first, each octopus increases energy by 1
+        let threshold = next.rules.threshold as u16;
         for row in 0..next.rows {
             for col in 0..next.cols {
                 next.state[row][col] += 1;
-                if next.state[row][col] > 9 {
-                    queue.push((row as usize, col as usize));
+                if next.state[row][col] > threshold {
+                    queue.push((row, col));
                 }
             }
         }
         // process flashes until none left
-        while !queue.is_empty() {
-            // dbg!(&next, &queue);
-            let flash = queue.pop().unwrap();
-            // println!("FLASH: {:?}", flash);
-            for dr in -1..=1isize {
-                for dc in -1..=1isize {
-                    if next.valid_pos(flash.0 as isize + dr, flash.1 as isize + dc) {
-                        let row = (flash.0 as isize + dr) as usize;
-                        let col = (flash.1 as isize + dc) as usize;
-                        if next.state[row][col] < 10 {
-                            next.state[row][col] += 1;
-                            if next.state[row][col] > 9 {
-                                queue.push((row, col));
-                            }
+        while let Some(flash) = queue.pop() {
+            for &(dr, dc) in next.rules.neighbourhood.offsets() {
+                if next.valid_pos(flash.0 as isize + dr, flash.1 as isize + dc) {
+                    let row = (flash.0 as isize + dr) as usize;
+                    let col = (flash.1 as isize + dc) as usize;
+                    if next.state[row][col] <= threshold {
+                        next.state[row][col] += 1;
+                        if next.state[row][col] > threshold {
+                            queue.push((row, col));
                         }
                     }
                 }
@@ -88,7 +229,7 @@ impl Grid {
         next.flashes = 0;
         for row in 0..next.rows {
             for col in 0..next.cols {
-                if next.state[row][col] > 9 {
+                if next.state[row][col] > threshold {
                     next.state[row][col] = 0;
                     next.flashes += 1
                 }
@@ -105,6 +246,34 @@ impl Grid {
     }
 }
 
+impl Simulation for Grid {
+    fn render(&self) -> String {
+        let mut s = String::new();
+        for row in &self.state {
+            for &oct in row {
+                let colour = match oct {
+                    0 => "1;97", // just flashed
+                    1..=5 => "34",
+                    6..=8 => "36",
+                    _ => "1;33", // about to flash
+                };
+                s += &paint(&oct.to_string(), colour);
+            }
+            s += "\n";
+        }
+        s + &format!("{} flashes, {} total\n", self.flashes, self.total_flashes)
+    }
+
+    // stops after the first step where every octopus flashes at once
+    fn advance(&mut self) -> bool {
+        if self.flashes == self.rows * self.cols {
+            return false;
+        }
+        *self = self.step();
+        true
+    }
+}
+
 impl fmt::Debug for Grid {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut s = String::new();
@@ -169,4 +338,53 @@ fn test() {
         grid = grid.step();
     }
     assert_eq!(1656, grid.total_flashes);
+
+    let grid = Grid::new(test_input2);
+    let series = grid.flash_series(195);
+    assert_eq!(1656, series[..100].iter().sum::<usize>());
+    assert_eq!(35, series[1]);
+    assert_eq!(Ok(195), grid.first_sync());
+    // once synchronised every step is all zeros + 1, so the cycle has started
+    let cycle = grid.find_cycle().unwrap();
+    assert!(cycle.start <= 195 && cycle.period == 10);
+
+    // with only 4 neighbours the two pairs of octopuses keep flashing out of
+    // step, the states loop without ever synchronising
+    let rules = Rules {
+        neighbourhood: Neighbourhood::VonNeumann,
+        ..Default::default()
+    };
+    let grid = Grid::with_rules("19\n91\n", rules);
+    assert_eq!(
+        Err(SyncError::Never(Cycle {
+            start: 0,
+            period: 8
+        })),
+        grid.first_sync()
+    );
+    assert_eq!(
+        "never synchronises, repeats every 8 steps from step 0",
+        grid.first_sync().unwrap_err().to_string()
+    );
+    let rules = Rules {
+        step_limit: 5,
+        ..rules
+    };
+    assert_eq!(
+        Err(SyncError::StepLimit(5)),
+        Grid::with_rules("10\n00\n", rules).first_sync() // would sync on step 9
+    );
+
+    let rules = Rules {
+        threshold: 3,
+        ..Default::default()
+    };
+    let grid = Grid::with_rules("000\n030\n000\n", rules);
+    assert_eq!(vec![1, 0, 9], grid.flash_series(3));
+    let rules = Rules {
+        threshold: u8::MAX,
+        ..Default::default()
+    };
+    let series = Grid::with_rules("9", rules).flash_series(250);
+    assert_eq!(Some(246), series.iter().position(|&flashes| flashes == 1));
 }
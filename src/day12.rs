@@ -1,28 +1,52 @@
+#[cfg(test)]
+use crate::rng::Rng;
 use crate::Part;
+use std::collections::HashMap;
 
 pub fn run(input: &str, part: Part) -> String {
     let system = parse_input(input);
-    format!(
-        "{}",
-        match part {
-            Part::One => part1(&system),
-            Part::Two => part2(&system),
+    let (answer, revisit) = match part {
+        Part::One => (part1(&system), Revisit::Single(1)),
+        Part::Two => (part2(&system), Revisit::Single(2)),
+    };
+    if !crate::verbose() {
+        return format!("{}", answer);
+    }
+    let options = PathOptions {
+        revisit,
+        ..Default::default()
+    };
+    let (mut shortest, mut longest): (Vec<&str>, Vec<&str>) = (vec![], vec![]);
+    for path in system.paths(&options) {
+        if shortest.is_empty() || path.len() < shortest.len() {
+            shortest = path.clone();
         }
+        if path.len() > longest.len() {
+            longest = path;
+        }
+    }
+    format!(
+        "{}\nshortest: {}\nlongest: {}",
+        answer,
+        shortest.join(","),
+        longest.join(",")
     )
 }
 
 fn part1(system: &CaveSystem) -> usize {
-    system.count_paths(&mut vec![0], Revisit::NotAllowed)
+    system.count_paths_memo(Revisit::Single(1))
 }
 
 fn part2(system: &CaveSystem) -> usize {
-    system.count_paths(&mut vec![0], Revisit::Allowed)
+    system.count_paths_memo(Revisit::Single(2))
 }
 
-#[derive(Clone, Copy)]
+// how often small caves may be visited on a single path
+#[derive(Clone, Copy, Debug, PartialEq)]
 enum Revisit {
-    Allowed,
-    NotAllowed,
+    // one small cave may be visited up to k times, all others only once.  Single(0) allows no
+    // small caves at all, so only paths through big caves remain.
+    Single(usize),
 }
 
 #[derive(Debug, PartialEq)]
@@ -45,19 +69,128 @@ struct CaveSystem {
     links: Vec<Vec<usize>>, // set of links between caves
 }
 
-// macro to push a cave id and then remove it to share vector in path search
-macro_rules! explore_path {
-    ($self:ident, $path:ident, $to_cave_id:expr, $revisit:expr) => {{
-        $path.push($to_cave_id);
-        let count = $self.count_paths(&mut $path, $revisit);
-        $path.pop();
-        count
-    }};
+// restrictions on the paths produced by CaveSystem::paths
+#[derive(Debug)]
+struct PathOptions {
+    revisit: Revisit,
+    must_visit: Vec<String>, // every one of these caves must be on the path
+    max_len: Option<usize>,  // most caves on a path, counting start and end
+    exclude: Vec<String>,    // none of these caves may be on the path
+}
+
+impl Default for PathOptions {
+    fn default() -> Self {
+        PathOptions {
+            revisit: Revisit::Single(1),
+            must_visit: vec![],
+            max_len: None,
+            exclude: vec![],
+        }
+    }
+}
+
+// visit counts for the path currently being explored
+struct Walk {
+    revisit: Revisit,
+    visits: Vec<usize>,
+    repeated: Option<usize>, // small cave visited more than once, if any
+}
+
+impl Walk {
+    fn new(system: &CaveSystem, revisit: Revisit) -> Walk {
+        Walk {
+            revisit,
+            visits: vec![0; system.caves.len()],
+            repeated: None,
+        }
+    }
+
+    fn can_enter(&self, system: &CaveSystem, cave_id: usize) -> bool {
+        let visits = self.visits[cave_id];
+        match system.cave(cave_id).cave_type {
+            CaveType::Start => false, // may not revisit
+            CaveType::Big | CaveType::End => true,
+            CaveType::Small => match self.revisit {
+                Revisit::Single(k) => {
+                    visits < k && (visits == 0 || self.repeated.is_none_or(|id| id == cave_id))
+                }
+            },
+        }
+    }
+
+    fn enter(&mut self, system: &CaveSystem, cave_id: usize) {
+        self.visits[cave_id] += 1;
+        if self.visits[cave_id] > 1 && system.cave(cave_id).cave_type == CaveType::Small {
+            self.repeated = Some(cave_id);
+        }
+    }
+
+    fn leave(&mut self, cave_id: usize) {
+        self.visits[cave_id] -= 1;
+        if self.visits[cave_id] <= 1 && self.repeated == Some(cave_id) {
+            self.repeated = None;
+        }
+    }
+}
+
+// iterator over every path from start to end, depth first
+struct Paths<'a> {
+    system: &'a CaveSystem,
+    walk: Walk,
+    path: Vec<usize>,      // caves on the current path, not including end
+    next_link: Vec<usize>, // for each cave on the path, the next link to try
+    must_visit: Vec<usize>,
+    excluded: Vec<bool>,
+    max_len: usize,
+}
+
+impl<'a> Iterator for Paths<'a> {
+    type Item = Vec<&'a str>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let system = self.system;
+        while let Some(&cave_id) = self.path.last() {
+            let link = self.next_link.last_mut().unwrap();
+            if *link == system.links[cave_id].len() {
+                // every way on from here has been tried
+                self.path.pop();
+                self.next_link.pop();
+                self.walk.leave(cave_id);
+                continue;
+            }
+            let to_cave_id = system.links[cave_id][*link];
+            *link += 1;
+            if self.excluded[to_cave_id] || !self.walk.can_enter(system, to_cave_id) {
+                continue;
+            }
+            if system.cave(to_cave_id).cave_type == CaveType::End {
+                if self.path.len() < self.max_len
+                    && self.must_visit.iter().all(|&id| self.walk.visits[id] > 0)
+                {
+                    let mut names: Vec<&str> = self
+                        .path
+                        .iter()
+                        .map(|&id| system.cave(id).name.as_str())
+                        .collect();
+                    names.push(&system.cave(to_cave_id).name);
+                    return Some(names);
+                }
+                continue;
+            }
+            // leave room for the end cave
+            if self.path.len() + 2 <= self.max_len {
+                self.walk.enter(system, to_cave_id);
+                self.path.push(to_cave_id);
+                self.next_link.push(0);
+            }
+        }
+        None
+    }
 }
 
 impl CaveSystem {
     fn add_cave(&mut self, name: &str) -> usize {
-        if let Some(i) = self.caves.iter().position(|cave| cave.name == name) {
+        if let Some(i) = self.cave_id(name) {
             return i;
         }
         let cave_type = if name == "start" {
@@ -86,47 +219,135 @@ impl CaveSystem {
         &self.caves[index]
     }
 
-    fn count_paths(&self, mut path: &mut Vec<usize>, revisit: Revisit) -> usize {
-        let this_cave_id = *path.last().unwrap();
-        if self.cave(this_cave_id).cave_type == CaveType::End {
-            // println!(
-            //     "{}",
-            //     path.iter()
-            //         .map(|i| self.cave(*i).name.clone())
-            //         .collect::<Vec<String>>()
-            //         .join(" > ")
-            // );
+    fn cave_id(&self, name: &str) -> Option<usize> {
+        self.caves.iter().position(|cave| cave.name == name)
+    }
+
+    fn count_paths(&self, revisit: Revisit) -> usize {
+        let mut walk = Walk::new(self, revisit);
+        walk.enter(self, 0);
+        self.count_paths_from(0, &mut walk)
+    }
+
+    fn count_paths_from(&self, cave_id: usize, walk: &mut Walk) -> usize {
+        if self.cave(cave_id).cave_type == CaveType::End {
             return 1; // reached the end, this path counts
         }
 
-        let v = self.links[this_cave_id]
-            .iter()
-            .map(|to_cave_id| {
-                let to_cave = self.cave(*to_cave_id);
-                match to_cave.cave_type {
-                    CaveType::Start => 0, // may not revisit
-                    CaveType::Big | CaveType::End => {
-                        explore_path!(self, path, *to_cave_id, revisit)
-                    }
-                    CaveType::Small => {
-                        if path.contains(to_cave_id) {
-                            match revisit {
-                                Revisit::Allowed => {
-                                    // only allowed once per path
-                                    explore_path!(self, path, *to_cave_id, Revisit::NotAllowed)
-                                }
-                                Revisit::NotAllowed => {
-                                    0 // no small revisits are allowed
-                                }
-                            }
-                        } else {
-                            explore_path!(self, path, *to_cave_id, revisit)
-                        }
+        let mut count = 0;
+        for &to_cave_id in &self.links[cave_id] {
+            if walk.can_enter(self, to_cave_id) {
+                walk.enter(self, to_cave_id);
+                count += self.count_paths_from(to_cave_id, walk);
+                walk.leave(to_cave_id);
+            }
+        }
+        count
+    }
+
+    // same as count_paths, but remembers the number of ways to the end from
+    // each (cave, small caves visited, revisit used) state so shared tails are
+    // only counted once.  only Single(1) and Single(2) with up to 64 small
+    // caves fit in that state, anything else falls back to count_paths.
+    fn count_paths_memo(&self, revisit: Revisit) -> usize {
+        let revisit_used = match revisit {
+            Revisit::Single(1) => true,
+            Revisit::Single(2) => false,
+            _ => return self.count_paths(revisit),
+        };
+        // give every small cave a bit in the visited mask
+        let mut small_bit = vec![None; self.caves.len()];
+        let mut bits = 0;
+        for (id, cave) in self.caves.iter().enumerate() {
+            if cave.cave_type == CaveType::Small {
+                if bits == 64 {
+                    return self.count_paths(revisit);
+                }
+                small_bit[id] = Some(bits);
+                bits += 1;
+            }
+        }
+        let mut cache = HashMap::new();
+        self.count_paths_memo_from(0, 0, revisit_used, &small_bit, &mut cache)
+    }
+
+    fn count_paths_memo_from(
+        &self,
+        cave_id: usize,
+        visited: u64,
+        revisit_used: bool,
+        small_bit: &[Option<u32>],
+        cache: &mut HashMap<(usize, u64, bool), usize>,
+    ) -> usize {
+        if self.cave(cave_id).cave_type == CaveType::End {
+            return 1; // reached the end, this path counts
+        }
+        if let Some(&count) = cache.get(&(cave_id, visited, revisit_used)) {
+            return count;
+        }
+
+        let mut count = 0;
+        for &to_cave_id in &self.links[cave_id] {
+            count += match (
+                self.cave(to_cave_id).cave_type == CaveType::Start,
+                small_bit[to_cave_id],
+            ) {
+                (true, _) => 0, // may not revisit
+                (false, None) => {
+                    self.count_paths_memo_from(to_cave_id, visited, revisit_used, small_bit, cache)
+                }
+                (false, Some(bit)) => {
+                    let mask = 1 << bit;
+                    if visited & mask == 0 {
+                        let visited = visited | mask;
+                        self.count_paths_memo_from(
+                            to_cave_id,
+                            visited,
+                            revisit_used,
+                            small_bit,
+                            cache,
+                        )
+                    } else if !revisit_used {
+                        self.count_paths_memo_from(to_cave_id, visited, true, small_bit, cache)
+                    } else {
+                        0
                     }
                 }
-            })
-            .sum();
-        v
+            };
+        }
+        cache.insert((cave_id, visited, revisit_used), count);
+        count
+    }
+
+    // every path from start to end allowed by the options, as cave names.
+    // a must_visit cave that doesn't exist means there are no paths.
+    fn paths(&self, options: &PathOptions) -> Paths<'_> {
+        let mut excluded = vec![false; self.caves.len()];
+        for id in options.exclude.iter().filter_map(|name| self.cave_id(name)) {
+            excluded[id] = true;
+        }
+        let must_visit: Option<Vec<usize>> = options
+            .must_visit
+            .iter()
+            .map(|name| self.cave_id(name))
+            .collect();
+        let mut walk = Walk::new(self, options.revisit);
+        let max_len = options.max_len.unwrap_or(usize::MAX);
+        let (path, next_link) = if must_visit.is_some() && !excluded[0] && max_len >= 2 {
+            walk.enter(self, 0);
+            (vec![0], vec![0])
+        } else {
+            (vec![], vec![])
+        };
+        Paths {
+            system: self,
+            walk,
+            path,
+            next_link,
+            must_visit: must_visit.unwrap_or_default(),
+            excluded,
+            max_len,
+        }
     }
 }
 
@@ -167,8 +388,7 @@ b-end
     assert_eq!(CaveType::Small, system.cave(2).cave_type);
     assert_eq!("end", system.cave(5).name);
     assert_eq!(CaveType::End, system.cave(5).cave_type);
-    assert_eq!(1, system.count_paths(&mut vec![5], Revisit::NotAllowed));
-    assert_eq!(10, system.count_paths(&mut vec![0], Revisit::NotAllowed));
+    assert_eq!(10, system.count_paths(Revisit::Single(1)));
     assert_eq!(10, part1(&system));
     assert_eq!(36, part2(&system));
 
@@ -211,4 +431,143 @@ start-RW
     let system3 = parse_input(test_input3);
     assert_eq!(226, part1(&system3));
     assert_eq!(3509, part2(&system3));
+
+    // enumeration agrees with counting for each revisit limit
+    for system in [&system, &system2, &system3] {
+        for revisit in [Revisit::Single(1), Revisit::Single(2), Revisit::Single(3)] {
+            let options = PathOptions {
+                revisit,
+                ..Default::default()
+            };
+            assert_eq!(system.count_paths(revisit), system.paths(&options).count());
+        }
+    }
+
+    let mut paths: Vec<String> = system
+        .paths(&PathOptions::default())
+        .map(|path| path.join(","))
+        .collect();
+    paths.sort();
+    assert_eq!("start,A,b,A,c,A,end", paths[0]);
+    assert_eq!("start,b,end", paths[9]);
+
+    let options = PathOptions {
+        must_visit: vec!["c".to_owned()],
+        max_len: Some(6),
+        exclude: vec!["b".to_owned()],
+        ..Default::default()
+    };
+    let paths: Vec<Vec<&str>> = system.paths(&options).collect();
+    assert_eq!(vec![vec!["start", "A", "c", "A", "end"]], paths);
+
+    let options = PathOptions {
+        must_visit: vec!["nowhere".to_owned()],
+        ..Default::default()
+    };
+    assert_eq!(0, system.paths(&options).count());
+    let options = PathOptions {
+        max_len: Some(3),
+        ..Default::default()
+    };
+    assert_eq!(2, system.paths(&options).count()); // start,A,end and start,b,end
+
+    for system in [&system, &system2, &system3] {
+        for revisit in [Revisit::Single(1), Revisit::Single(2), Revisit::Single(3)] {
+            assert_eq!(
+                system.count_paths(revisit),
+                system.count_paths_memo(revisit)
+            );
+        }
+    }
+
+    for seed in 1..20 {
+        let system = parse_input(&random_cave_system(8, 3, seed));
+        for revisit in [Revisit::Single(1), Revisit::Single(2)] {
+            assert_eq!(
+                system.count_paths(revisit),
+                system.count_paths_memo(revisit)
+            );
+        }
+    }
+
+    // too many small caves for the memo's bit mask: start-c0-c1-...-c69-end
+    let mut names = vec!["start".to_string()];
+    names.extend((0..70).map(|i| format!("c{}", i)));
+    names.push("end".to_string());
+    let links: Vec<String> = names
+        .windows(2)
+        .map(|w| format!("{}-{}", w[0], w[1]))
+        .collect();
+    let system = parse_input(&links.join("\n"));
+    assert_eq!(1, system.count_paths_memo(Revisit::Single(2)));
+
+    // no small caves allowed: start-A-end is the only way through
+    let system = parse_input("start-A\nA-b\nA-end\nstart-c\nc-end");
+    assert_eq!(1, system.count_paths(Revisit::Single(0)));
+    assert_eq!(3, system.count_paths(Revisit::Single(1)));
+}
+
+// random cave system with the given number of small and big caves.  big caves
+// are never linked to each other, otherwise there would be endless paths.
+#[cfg(test)]
+fn random_cave_system(small: usize, big: usize, seed: u64) -> String {
+    let mut rng = Rng::new(seed);
+    let small_names: Vec<String> = (0..small).map(|i| format!("s{}", i)).collect();
+    let big_names: Vec<String> = (0..big).map(|i| format!("B{}", i)).collect();
+    let mut links = vec![];
+    for (i, name) in small_names.iter().enumerate() {
+        // chain the small caves together so everything is reachable
+        if i > 0 {
+            links.push(format!("{}-{}", small_names[i - 1], name));
+        }
+        links.push(format!("{}-{}", name, small_names[rng.below(small)]));
+        links.push(format!("{}-{}", name, big_names[rng.below(big)]));
+    }
+    for name in small_names.iter().chain(&big_names).take(small / 4 + 1) {
+        links.push(format!("start-{}", name));
+    }
+    for name in small_names
+        .iter()
+        .rev()
+        .chain(&big_names)
+        .take(small / 4 + 1)
+    {
+        links.push(format!("{}-end", name));
+    }
+    // drop self links and duplicates
+    links.retain(|link| {
+        let (left, right) = link.split_once('-').unwrap();
+        left != right
+    });
+    links.sort();
+    links.dedup();
+    links.join("\n")
+}
+
+#[test]
+#[ignore]
+// cargo test --release bench_count_paths -- --ignored --nocapture
+fn bench_count_paths() {
+    use std::time::Instant;
+
+    for (small, big) in [(10, 3), (12, 3), (14, 4), (16, 4), (18, 5)] {
+        let system = parse_input(&random_cave_system(small, big, 0x2021_1212));
+
+        let t0 = Instant::now();
+        let memo = system.count_paths_memo(Revisit::Single(2));
+        let memo_time = t0.elapsed().as_secs_f64();
+
+        let plain = if small <= 14 {
+            let t0 = Instant::now();
+            assert_eq!(memo, system.count_paths(Revisit::Single(2)));
+            format!("{:.3}s", t0.elapsed().as_secs_f64())
+        } else {
+            "skipped".to_string()
+        };
+
+        println!(
+            "{:2} small {:2} big caves: {:20} paths, plain {:>8}, memoised {:.3}s",
+            small, big, memo, plain, memo_time
+        );
+    }
 }
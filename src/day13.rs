@@ -1,17 +1,36 @@
+use crate::raster::{Raster, BLACK, WHITE};
 use crate::Part;
 use std::collections::HashSet;
+use std::fmt;
 
 pub fn run(input: &str, part: Part) -> String {
-    let (mut paper, folds) = parse_input(input);
-    match part {
-        Part::One => format!("{}", fold_paper(&paper, folds[0]).len()),
-        Part::Two => {
-            for fold in folds {
-                paper = fold_paper(&paper, fold);
-            }
-            print_paper(&paper)
-        }
+    let (paper, folds) = parse_input(input);
+    let folds = match part {
+        Part::One => &folds[..1],
+        Part::Two => &folds[..],
+    };
+    let folded = match fold_all(&paper, folds) {
+        Ok(folded) => folded,
+        Err(e) => return e.to_string(),
+    };
+    let answer = match part {
+        Part::One => format!("{}", folded.len()),
+        Part::Two => match ocr(&folded) {
+            Ok(code) if crate::verbose() => format!("{}\n{}", code, print_paper(&folded)),
+            Ok(code) => code,
+            Err(e) => format!("{}\n{}", e, print_paper(&folded)),
+        },
+    };
+    if !crate::verbose() {
+        return answer;
     }
+    // these folds have already worked once
+    let steps: Vec<String> = fold_history(&paper, folds)
+        .unwrap()
+        .iter()
+        .map(|step| step.to_string())
+        .collect();
+    format!("{}\n{}", answer.trim_end_matches('\n'), steps.join("\n"))
 }
 
 #[derive(Clone, Copy, Debug, PartialEq)]
@@ -20,9 +39,153 @@ enum Fold {
     Y(usize),
 }
 
+impl fmt::Display for Fold {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Fold::X(x) => write!(f, "fold along x={}", x),
+            Fold::Y(y) => write!(f, "fold along y={}", y),
+        }
+    }
+}
+
 // set of dots are (row, column)
 type Paper = HashSet<(usize, usize)>;
 
+// (rows, columns) of the sheet of paper.  folding can leave it larger than
+// the dots on it, when the edge that was folded over had no dots near it.
+type Size = (usize, usize);
+
+// the input doesn't say how big the paper is, so it is taken to end at the
+// furthest dots
+fn unfolded_size(paper: &Paper) -> Size {
+    (
+        paper.iter().map(|(row, _)| row + 1).max().unwrap_or(0),
+        paper.iter().map(|(_, col)| col + 1).max().unwrap_or(0),
+    )
+}
+
+#[derive(Debug, PartialEq)]
+enum FoldError {
+    OutsidePaper { fold: Fold, last: usize }, // last row or column of the paper
+    DotOnLine { fold: Fold, dot: (usize, usize) },
+}
+
+impl fmt::Display for FoldError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FoldError::OutsidePaper { fold, last } => {
+                write!(f, "{} is outside the paper, which ends at {}", fold, last)
+            }
+            FoldError::DotOnLine { fold, dot } => {
+                write!(f, "{} goes through the dot at {},{}", fold, dot.1, dot.0)
+            }
+        }
+    }
+}
+
+// state of the paper after one fold
+#[derive(Debug, PartialEq)]
+struct FoldStep {
+    fold: Fold,
+    dots: usize,
+    top_left: (usize, usize), // (row, column) of the bounding box of the dots
+    bottom_right: (usize, usize),
+}
+
+impl fmt::Display for FoldStep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "{}: {} dots in rows {}-{}, columns {}-{}",
+            self.fold,
+            self.dots,
+            self.top_left.0,
+            self.bottom_right.0,
+            self.top_left.1,
+            self.bottom_right.1
+        )
+    }
+}
+
+// the 4x6 block letters used by the puzzles, letters are 5 columns apart
+const GLYPHS: [(char, &str); 17] = [
+    ('A', ".##.#..##..######..##..#"),
+    ('B', "###.#..####.#..##..####."),
+    ('C', ".##.#..##...#...#..#.##."),
+    ('E', "#####...###.#...#...####"),
+    ('F', "#####...###.#...#...#..."),
+    ('G', ".##.#..##...#.###..#.###"),
+    ('H', "#..##..######..##..##..#"),
+    ('I', ".###..#...#...#...#..###"),
+    ('J', "..##...#...#...##..#.##."),
+    ('K', "#..##.#.##..#.#.#.#.#..#"),
+    ('L', "#...#...#...#...#...####"),
+    ('O', ".##.#..##..##..##..#.##."),
+    ('P', "###.#..##..####.#...#..."),
+    ('R', "###.#..##..####.#.#.#..#"),
+    ('S', ".####...#....##....####."),
+    ('U', "#..##..##..##..##..#.##."),
+    ('Z', "####...#..#..#..#...####"),
+];
+
+#[derive(Debug, PartialEq)]
+enum OcrError {
+    Empty,
+    TooTall(usize),
+    UnknownGlyph { index: usize, glyph: String },
+}
+
+impl fmt::Display for OcrError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            OcrError::Empty => write!(f, "no dots to read"),
+            OcrError::TooTall(rows) => write!(f, "letters are 6 rows high, found {} rows", rows),
+            OcrError::UnknownGlyph { index, glyph } => {
+                write!(f, "unrecognised glyph {}:\n{}", index + 1, glyph)
+            }
+        }
+    }
+}
+
+// read the code spelt out by the dots on the paper
+fn ocr(paper: &Paper) -> Result<String, OcrError> {
+    let rows = paper
+        .iter()
+        .map(|(row, _)| row + 1)
+        .max()
+        .ok_or(OcrError::Empty)?;
+    if rows > 6 {
+        return Err(OcrError::TooTall(rows));
+    }
+    let cols = paper.iter().map(|(_, col)| col + 1).max().unwrap();
+    (0..cols.div_ceil(5))
+        .map(|index| {
+            let pixel = |row, col| {
+                if paper.contains(&(row, col)) {
+                    '#'
+                } else {
+                    '.'
+                }
+            };
+            let pattern: String = (0..6)
+                .flat_map(|row| (0..4).map(move |col| pixel(row, index * 5 + col)))
+                .collect();
+            let gap_clear = (0..6).all(|row| !paper.contains(&(row, index * 5 + 4)));
+            GLYPHS
+                .iter()
+                .find(|(_, glyph)| gap_clear && *glyph == pattern)
+                .map(|(c, _)| *c)
+                .ok_or_else(|| OcrError::UnknownGlyph {
+                    index,
+                    glyph: (0..6)
+                        .map(|row| (0..5).map(|col| pixel(row, index * 5 + col)).collect())
+                        .collect::<Vec<String>>()
+                        .join("\n"),
+                })
+        })
+        .collect()
+}
+
 fn print_paper(paper: &Paper) -> String {
     let mut s = String::new();
     let maxrow = *paper.iter().map(|(row, _)| row).max().unwrap();
@@ -40,31 +203,102 @@ fn print_paper(paper: &Paper) -> String {
     s
 }
 
-fn fold_paper(paper: &Paper, fold: Fold) -> Paper {
-    let mut new = Paper::new();
-    match fold {
-        Fold::Y(y) => {
-            for (row, col) in paper.iter() {
-                if *row > y {
-                    // any point BELOW the fold moves up
-                    new.insert((y - (row - y), *col));
-                } else {
-                    new.insert((*row, *col));
-                }
-            }
-        }
-        Fold::X(x) => {
-            for (row, col) in paper.iter() {
-                if *col > x {
-                    // any point to the RIGHT of the fold moves left
-                    new.insert((*row, x - (col - x)));
-                } else {
-                    new.insert((*row, *col));
-                }
-            }
-        }
+// the paper before folding and after each fold
+pub fn frames(input: &str) -> Result<Vec<Raster>, String> {
+    let (mut paper, folds) = parse_input(input);
+    let mut size = unfolded_size(&paper);
+    let mut frames = vec![paper_raster(&paper)];
+    for fold in folds {
+        (paper, size) = fold_paper(&paper, size, fold).map_err(|e| e.to_string())?;
+        frames.push(paper_raster(&paper));
+    }
+    Ok(frames)
+}
+
+fn paper_raster(paper: &Paper) -> Raster {
+    let rows = paper.iter().map(|(row, _)| row + 1).max().unwrap_or(0);
+    let cols = paper.iter().map(|(_, col)| col + 1).max().unwrap_or(0);
+    let mut raster = Raster::new(cols, rows, BLACK);
+    for &(row, col) in paper {
+        raster.set(col, row, WHITE);
+    }
+    raster
+}
+
+// fold the paper along a line, the bottom or right part is folded over onto
+// the rest.  if that part is the larger one the folded paper extends past the
+// old edge, so every dot is shifted to keep coordinates from going negative.
+// returns the dots and the size of the folded paper.
+fn fold_paper(paper: &Paper, size: Size, fold: Fold) -> Result<(Paper, Size), FoldError> {
+    // work on (along, across) so both directions share the same code,
+    // swapping twice gets back to (row, column)
+    let line = match fold {
+        Fold::X(x) => x,
+        Fold::Y(y) => y,
+    };
+    let split = |&(row, col): &(usize, usize)| match fold {
+        Fold::X(_) => (col, row),
+        Fold::Y(_) => (row, col),
     };
-    new
+    let (length, width) = split(&size);
+    if line >= length {
+        return Err(FoldError::OutsidePaper {
+            fold,
+            last: length.saturating_sub(1),
+        });
+    }
+    if let Some(&dot) = paper.iter().find(|dot| split(dot).0 == line) {
+        return Err(FoldError::DotOnLine { fold, dot });
+    }
+    let last = length - 1;
+    let shift = (last - line).saturating_sub(line);
+
+    let folded = paper
+        .iter()
+        .map(|dot| {
+            let (along, across) = split(dot);
+            let along = if along > line {
+                // any point BELOW or to the RIGHT of the fold is mirrored
+                line + shift - (along - line)
+            } else {
+                along + shift
+            };
+            split(&(along, across))
+        })
+        .collect();
+    Ok((folded, split(&(line + shift, width))))
+}
+
+fn fold_all(paper: &Paper, folds: &[Fold]) -> Result<Paper, FoldError> {
+    folds
+        .iter()
+        .try_fold(
+            (paper.clone(), unfolded_size(paper)),
+            |(paper, size), &fold| fold_paper(&paper, size, fold),
+        )
+        .map(|(paper, _)| paper)
+}
+
+// dot count and bounding box after each fold
+fn fold_history(paper: &Paper, folds: &[Fold]) -> Result<Vec<FoldStep>, FoldError> {
+    let mut size = unfolded_size(paper);
+    let mut paper = paper.clone();
+    let mut history = vec![];
+    for &fold in folds {
+        (paper, size) = fold_paper(&paper, size, fold)?;
+        let rows = paper.iter().map(|dot| dot.0);
+        let cols = paper.iter().map(|dot| dot.1);
+        history.push(FoldStep {
+            fold,
+            dots: paper.len(),
+            top_left: (
+                rows.clone().min().unwrap_or(0),
+                cols.clone().min().unwrap_or(0),
+            ),
+            bottom_right: (rows.max().unwrap_or(0), cols.max().unwrap_or(0)),
+        });
+    }
+    Ok(history)
 }
 
 fn parse_input(input: &str) -> (Paper, Vec<Fold>) {
@@ -120,9 +354,27 @@ fold along x=5
     assert_eq!(2, folds.len());
     assert_eq!(Fold::Y(7), folds[0]);
     assert_eq!(Fold::X(5), folds[1]);
-    let paper = fold_paper(&paper, folds[0]);
+    let history = fold_history(&paper, &folds).unwrap();
+    assert_eq!(
+        FoldStep {
+            fold: Fold::Y(7),
+            dots: 17,
+            top_left: (0, 0),
+            bottom_right: (4, 10),
+        },
+        history[0]
+    );
+    assert_eq!(16, history[1].dots);
+    assert_eq!((4, 4), history[1].bottom_right);
+    assert_eq!(
+        "fold along x=5: 16 dots in rows 0-4, columns 0-4",
+        history[1].to_string()
+    );
+    let (paper, size) = fold_paper(&paper, unfolded_size(&paper), folds[0]).unwrap();
     assert_eq!(17, paper.len());
-    let paper = fold_paper(&paper, folds[1]);
+    assert_eq!((7, 11), size);
+    let (paper, size) = fold_paper(&paper, size, folds[1]).unwrap();
+    assert_eq!((7, 5), size);
     assert_eq!(
         "\
 █████
@@ -133,4 +385,57 @@ fold along x=5
 ",
         print_paper(&paper)
     );
+    assert_eq!(
+        Err(OcrError::UnknownGlyph {
+            index: 0,
+            glyph: "#####\n#...#\n#...#\n#...#\n#####\n.....".to_string()
+        }),
+        ocr(&paper)
+    );
+    assert_eq!(Err(OcrError::Empty), ocr(&Paper::new()));
+
+    // write letters onto paper and read them back
+    let letters: String = GLYPHS.iter().map(|(c, _)| c).collect();
+    let mut paper = Paper::new();
+    for (index, (_, glyph)) in GLYPHS.iter().enumerate() {
+        for (i, c) in glyph.chars().enumerate() {
+            if c == '#' {
+                paper.insert((i / 4, index * 5 + i % 4));
+            }
+        }
+    }
+    assert_eq!(Ok(letters), ocr(&paper));
+    paper.insert((6, 0));
+    assert_eq!(Err(OcrError::TooTall(7)), ocr(&paper));
+
+    // folding near the top flips the longer bottom part up over it
+    let paper: Paper = [(0, 0), (1, 1), (5, 2)].into_iter().collect();
+    let size = unfolded_size(&paper);
+    let expected: Paper = [(1, 0), (2, 1), (0, 2)].into_iter().collect();
+    assert_eq!(Ok((expected, (3, 3))), fold_paper(&paper, size, Fold::Y(2)));
+    let folded = fold_paper(&paper, size, Fold::X(0));
+    assert_eq!(
+        Err(FoldError::DotOnLine {
+            fold: Fold::X(0),
+            dot: (0, 0)
+        }),
+        folded
+    );
+    let folded = fold_paper(&paper, size, Fold::X(3));
+    assert_eq!(
+        "fold along x=3 is outside the paper, which ends at 2",
+        folded.unwrap_err().to_string()
+    );
+
+    // after folding at 7, columns 5 and 6 are still paper even though no dot
+    // ended up there, so folding at 5 is allowed
+    let paper: Paper = [(0, 0), (0, 10)].into_iter().collect();
+    let folded = fold_all(&paper, &[Fold::X(7), Fold::X(5)]).unwrap();
+    let expected: Paper = [(0, 0), (0, 4)].into_iter().collect();
+    assert_eq!(expected, folded);
+    assert_eq!(3, frames(test_input).unwrap().len());
+    assert_eq!(
+        Err("fold along x=3 is outside the paper, which ends at 2".to_string()),
+        frames("0,0\n1,1\n2,5\n\nfold along x=3\n")
+    );
 }
@@ -1,33 +1,88 @@
 use crate::Part;
+use num_bigint::BigUint;
+use std::collections::hash_map::Entry;
+use std::collections::BTreeMap;
 use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fmt;
 
 pub fn run(input: &str, part: Part) -> String {
-    let (polymer, rules) = parse_input(input);
-    format!(
-        "{}",
-        apply(
-            &polymer,
-            &rules,
-            match part {
-                Part::One => 10,
-                Part::Two => 40,
+    let (template, rules, problems) = parse_input(input);
+    let polymer = Polymer::new(&template);
+    let times = match part {
+        Part::One => 10,
+        Part::Two => 40,
+    };
+    let answer = apply(&polymer, &rules, times);
+    if !crate::verbose() {
+        return format!("{}", answer);
+    }
+    let tally = histogram(&polymer, &rules, times);
+    let counts: Vec<String> = tally.iter().map(|(c, n)| format!("{} {}", c, n)).collect();
+    let mut lines = vec![answer.to_string(), counts.join(", ")];
+    // part 2's polymer is far too long to expand, and the rules were already checked in part 1
+    if let Part::Two = part {
+        return lines.join("\n");
+    }
+    // cross-check the pair counts against the real polymer while it is still small
+    lines.push(match expand(&template, &rules, times as usize, 100_000) {
+        Some(expanded) => {
+            let mut real: BTreeMap<char, BigUint> = BTreeMap::new();
+            for c in expanded.chars() {
+                *real.entry(c).or_default() += 1u32;
             }
-        )
-    )
+            format!(
+                "expanded polymer has {} elements, {}",
+                expanded.len(),
+                if real == tally {
+                    "same counts"
+                } else {
+                    "different counts"
+                }
+            )
+        }
+        None => "polymer is too long to expand".to_string(),
+    });
+    lines.extend(problems.iter().map(|p| p.to_string()));
+    lines.join("\n")
 }
 
-fn apply(polymer: &Polymer, rules: &RuleSet, times: usize) -> usize {
-    let mut p: Polymer = polymer.apply(rules);
-    for _ in 1..times {
-        p = p.apply(rules);
-    }
-    let tally = p.tally();
+fn apply(polymer: &Polymer, rules: &RuleSet, times: u64) -> BigUint {
+    let tally = polymer.apply_times(rules, times).tally();
     tally.values().max().unwrap() - tally.values().min().unwrap()
 }
 
+// number of each element in the polymer after `times` steps
+fn histogram(polymer: &Polymer, rules: &RuleSet, times: u64) -> BTreeMap<char, BigUint> {
+    polymer.apply_times(rules, times).tally()
+}
+
+// Build the actual polymer string by inserting elements step by step.  Its length roughly
+// doubles each step, so this gives up (returning None) once it would be longer than `max_len`.
+fn expand(template: &str, rules: &RuleSet, times: usize, max_len: usize) -> Option<String> {
+    let mut polymer: Vec<char> = template.chars().collect();
+    for _ in 0..times {
+        let mut next = Vec::with_capacity(polymer.len() * 2);
+        for (i, &c) in polymer.iter().enumerate() {
+            next.push(c);
+            if let Some(&ins) = polymer.get(i + 1).and_then(|&d| rules.get(&(c, d))) {
+                next.push(ins);
+            }
+        }
+        if next.len() > max_len {
+            return None;
+        }
+        polymer = next;
+    }
+    Some(polymer.into_iter().collect())
+}
+
 // store polymer as the numbers of element pairs.  the final character
 // is paired with a '$' indicating the end of the polymer.
-struct Polymer(HashMap<(char, char), usize>);
+#[derive(Debug, PartialEq)]
+struct Polymer(HashMap<(char, char), BigUint>);
+
+type Matrix = Vec<Vec<BigUint>>;
 
 impl Polymer {
     fn new(input: &str) -> Polymer {
@@ -35,59 +90,219 @@ impl Polymer {
         let chars: Vec<char> = input.chars().collect();
         if !chars.is_empty() {
             for i in 0..(chars.len() - 1) {
-                *hash.entry((chars[i], chars[i + 1])).or_insert(0) += 1;
+                *hash.entry((chars[i], chars[i + 1])).or_default() += 1u32;
             }
-            hash.insert((chars[chars.len() - 1], '$'), 1);
+            hash.insert((chars[chars.len() - 1], '$'), BigUint::from(1u32));
         }
         Polymer(hash)
     }
 
-    fn apply(&self, rules: &RuleSet) -> Polymer {
-        let mut hash: HashMap<(char, char), usize> = HashMap::new();
-        for (pair, count) in self.0.iter() {
-            if pair.1 == '$' {
-                hash.insert(*pair, *count);
-            } else if let Some(ins) = rules.get(&(pair.0, pair.1)) {
-                *hash.entry((pair.0, *ins)).or_insert(0) += count;
-                *hash.entry((*ins, pair.1)).or_insert(0) += count;
-            } else {
-                panic!("No rule for {:?}", pair);
+    // the pairs each pair becomes after one step.  pairs without a rule are left alone.
+    fn successors(pair: (char, char), rules: &RuleSet) -> Vec<(char, char)> {
+        match rules.get(&pair) {
+            Some(&ins) => vec![(pair.0, ins), (ins, pair.1)],
+            None => vec![pair],
+        }
+    }
+
+    // Apply the rules `times` times.  Each pair turns into the same pairs every step, so this
+    // is a linear map on the pair counts: build its matrix over every pair that can occur, and
+    // raise it to the power `times` by repeated squaring.
+    fn apply_times(&self, rules: &RuleSet, times: u64) -> Polymer {
+        let mut pairs: Vec<(char, char)> = self.0.keys().copied().collect();
+        pairs.sort_unstable();
+        let mut index: HashMap<(char, char), usize> =
+            pairs.iter().enumerate().map(|(i, &p)| (p, i)).collect();
+        let mut i = 0;
+        while i < pairs.len() {
+            for next in Polymer::successors(pairs[i], rules) {
+                if let Entry::Vacant(e) = index.entry(next) {
+                    e.insert(pairs.len());
+                    pairs.push(next);
+                }
+            }
+            i += 1;
+        }
+
+        let n = pairs.len();
+        let mut step: Matrix = vec![vec![BigUint::default(); n]; n];
+        for (from, &pair) in pairs.iter().enumerate() {
+            for next in Polymer::successors(pair, rules) {
+                step[index[&next]][from] += 1u32;
+            }
+        }
+        let power = matrix_pow(&step, times);
+
+        let mut hash = HashMap::new();
+        for (to, row) in power.iter().enumerate() {
+            let count: BigUint = self
+                .0
+                .iter()
+                .map(|(pair, count)| &row[index[pair]] * count)
+                .sum();
+            if count != BigUint::default() {
+                hash.insert(pairs[to], count);
             }
         }
         Polymer(hash)
     }
 
-    fn tally(&self) -> HashMap<char, usize> {
-        let mut counts = HashMap::new();
+    fn tally(&self) -> BTreeMap<char, BigUint> {
+        let mut counts = BTreeMap::new();
         for (pair, count) in self.0.iter() {
-            *counts.entry(pair.0).or_insert(0) += count;
+            *counts.entry(pair.0).or_default() += count;
         }
         counts
     }
 }
 
+fn matrix_mul(a: &Matrix, b: &Matrix) -> Matrix {
+    let n = a.len();
+    let zero = BigUint::default();
+    let mut c: Matrix = vec![vec![BigUint::default(); n]; n];
+    for i in 0..n {
+        for k in 0..n {
+            if a[i][k] == zero {
+                continue;
+            }
+            for j in 0..n {
+                if b[k][j] != zero {
+                    c[i][j] += &a[i][k] * &b[k][j];
+                }
+            }
+        }
+    }
+    c
+}
+
+fn matrix_pow(matrix: &Matrix, mut exp: u64) -> Matrix {
+    let n = matrix.len();
+    let mut result: Matrix = (0..n)
+        .map(|i| (0..n).map(|j| BigUint::from((i == j) as u32)).collect())
+        .collect();
+    let mut base = matrix.clone();
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = matrix_mul(&result, &base);
+        }
+        exp >>= 1;
+        if exp > 0 {
+            base = matrix_mul(&base, &base);
+        }
+    }
+    result
+}
+
 // Rules are a map of character pairs to the character to insert between them
 type RuleSet = HashMap<(char, char), char>;
 
-fn parse_input(input: &str) -> (Polymer, RuleSet) {
+// Things in a rule set which are probably mistakes.  Line numbers start at 1.
+#[derive(Debug, PartialEq)]
+enum RuleProblem {
+    // the same rule appears again
+    Duplicate {
+        line: usize,
+        pair: (char, char),
+    },
+    // a second rule for a pair inserts something different; the first one is used
+    Conflicting {
+        line: usize,
+        pair: (char, char),
+        first: char,
+        second: char,
+    },
+    // the pair can never appear in a polymer grown from the template
+    Unreachable {
+        line: usize,
+        pair: (char, char),
+    },
+}
+
+impl fmt::Display for RuleProblem {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RuleProblem::Duplicate { line, pair } => {
+                write!(f, "line {}: duplicate rule for {}{}", line, pair.0, pair.1)
+            }
+            RuleProblem::Conflicting {
+                line,
+                pair,
+                first,
+                second,
+            } => write!(
+                f,
+                "line {}: {}{} -> {} conflicts with earlier {}{} -> {}",
+                line, pair.0, pair.1, second, pair.0, pair.1, first
+            ),
+            RuleProblem::Unreachable { line, pair } => {
+                write!(f, "line {}: {}{} never appears", line, pair.0, pair.1)
+            }
+        }
+    }
+}
+
+// every pair which can ever appear in a polymer grown from the template
+fn reachable_pairs(template: &str, rules: &RuleSet) -> HashSet<(char, char)> {
+    let chars: Vec<char> = template.chars().collect();
+    let mut todo: Vec<(char, char)> = chars.windows(2).map(|w| (w[0], w[1])).collect();
+    let mut reachable = HashSet::new();
+    while let Some(pair) = todo.pop() {
+        if reachable.insert(pair) {
+            todo.extend(Polymer::successors(pair, rules));
+        }
+    }
+    reachable
+}
+
+// returns (template, rules, problems found in the rules)
+fn parse_input(input: &str) -> (String, RuleSet, Vec<RuleProblem>) {
     let mut section = 0;
-    let mut polymer = Polymer::new("");
+    let mut template = String::new();
     let mut ruleset = RuleSet::new();
+    let mut lines: HashMap<(char, char), usize> = HashMap::new();
+    let mut problems = vec![];
 
-    for line in input.lines() {
+    for (n, line) in input.lines().enumerate() {
         if line.is_empty() {
             section = 1;
         } else if section == 0 {
-            polymer = Polymer::new(line);
+            template = line.to_string();
         } else {
             let rule: Vec<&str> = line.split(" -> ").collect();
             let from: Vec<char> = rule[0].chars().collect();
             let to: char = rule[1].chars().next().unwrap();
-            ruleset.insert((from[0], from[1]), to);
+            let pair = (from[0], from[1]);
+            match ruleset.get(&pair) {
+                Some(&first) if first == to => {
+                    problems.push(RuleProblem::Duplicate { line: n + 1, pair })
+                }
+                Some(&first) => problems.push(RuleProblem::Conflicting {
+                    line: n + 1,
+                    pair,
+                    first,
+                    second: to,
+                }),
+                None => {
+                    ruleset.insert(pair, to);
+                    lines.insert(pair, n + 1);
+                }
+            }
         }
     }
 
-    (polymer, ruleset)
+    let reachable = reachable_pairs(&template, &ruleset);
+    let mut unreachable: Vec<RuleProblem> = lines
+        .iter()
+        .filter(|(pair, _)| !reachable.contains(pair))
+        .map(|(&pair, &line)| RuleProblem::Unreachable { line, pair })
+        .collect();
+    unreachable.sort_unstable_by_key(|p| match p {
+        RuleProblem::Unreachable { line, .. } => *line,
+        _ => 0,
+    });
+    problems.extend(unreachable);
+
+    (template, ruleset, problems)
 }
 
 #[test]
@@ -112,8 +327,93 @@ BC -> B
 CC -> N
 CN -> C
 ";
-    let (polymer, rules) = parse_input(test_input);
+    let (template, rules, problems) = parse_input(test_input);
+    let polymer = Polymer::new(&template);
     assert_eq!(16, rules.len());
-    assert_eq!(1588, apply(&polymer, &rules, 10));
-    assert_eq!(2188189693529, apply(&polymer, &rules, 40));
+    assert!(problems.is_empty());
+    assert_eq!(BigUint::from(1588u32), apply(&polymer, &rules, 10));
+    assert_eq!(BigUint::from(2188189693529u64), apply(&polymer, &rules, 40));
+
+    let counts = |pairs: &[(char, u64)]| -> BTreeMap<char, BigUint> {
+        pairs.iter().map(|&(c, n)| (c, BigUint::from(n))).collect()
+    };
+    assert_eq!(
+        counts(&[('B', 2), ('C', 2), ('H', 1), ('N', 2)]),
+        histogram(&polymer, &rules, 1)
+    );
+    assert_eq!(
+        counts(&[('B', 1749), ('C', 298), ('H', 161), ('N', 865)]),
+        histogram(&polymer, &rules, 10)
+    );
+    // the polymer doubles in length (less one) every step
+    let big = histogram(&polymer, &rules, 5000);
+    let length: BigUint = big.values().sum();
+    assert_eq!((BigUint::from(3u32) << 5000usize) + 1u32, length);
+
+    assert_eq!(
+        Some("NBBNBNBBCCNBCNCCNBBNBBNBBBNBBNBBCBHCBHHNHCBBCBHCB".to_string()),
+        expand(&template, &rules, 4, 100)
+    );
+    assert_eq!(None, expand(&template, &rules, 10, 100));
+    // the pair counting model agrees with the real thing
+    for times in 0..=10 {
+        let real = expand(&template, &rules, times, 10000).unwrap();
+        assert_eq!(
+            Polymer::new(&real),
+            polymer.apply_times(&rules, times as u64)
+        );
+    }
+
+    // a partial rule set, with some mistakes in it
+    let test_input = "\
+NNCB
+
+NN -> C
+NC -> B
+CB -> H
+NN -> C
+NC -> N
+HH -> N
+";
+    let (template, rules, problems) = parse_input(test_input);
+    assert_eq!(
+        vec![
+            RuleProblem::Duplicate {
+                line: 6,
+                pair: ('N', 'N')
+            },
+            RuleProblem::Conflicting {
+                line: 7,
+                pair: ('N', 'C'),
+                first: 'B',
+                second: 'N'
+            },
+            RuleProblem::Unreachable {
+                line: 8,
+                pair: ('H', 'H')
+            },
+        ],
+        problems
+    );
+    assert_eq!(
+        "line 7: NC -> N conflicts with earlier NC -> B",
+        problems[1].to_string()
+    );
+    // NNCB -> NCNBCHB -> NBCNBCHB, after which no pair has a rule
+    assert_eq!(
+        Some("NCNBCHB".to_string()),
+        expand(&template, &rules, 1, 100)
+    );
+    assert_eq!(
+        Some("NBCNBCHB".to_string()),
+        expand(&template, &rules, 5, 100)
+    );
+    let polymer = Polymer::new(&template);
+    for times in 0..=5 {
+        let real = expand(&template, &rules, times, 100).unwrap();
+        assert_eq!(
+            Polymer::new(&real),
+            polymer.apply_times(&rules, times as u64)
+        );
+    }
 }
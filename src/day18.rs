@@ -1,49 +1,181 @@
+#[cfg(test)]
+use crate::rng::Rng;
 use crate::Part;
+use std::cmp::{Ordering, Reverse};
+use std::collections::BinaryHeap;
 use std::fmt;
 use std::ops::Add;
+use std::thread;
 
 pub fn run(input: &str, part: Part) -> String {
-    let sf_numbers = parse_input(input);
-    format!(
-        "{}",
-        match part {
+    let sf_numbers: Vec<SfTree> = parse_input(input);
+    if !crate::verbose() || sf_numbers.len() < 2 {
+        let answer = match part {
             Part::One => part1(&sf_numbers),
             Part::Two => part2(&sf_numbers),
+        };
+        return format!("{}", answer);
+    }
+    // show the best few pairs, then how the first addition of the list, or the best pair, is
+    // reduced
+    let mut lines = vec![];
+    let (left, right) = match part {
+        Part::One => {
+            lines.push(part1(&sf_numbers).to_string());
+            (0, 1)
+        }
+        Part::Two => {
+            let best = best_pairs(&sf_numbers, 5);
+            lines.push(best[0].magnitude.to_string());
+            lines.extend(best.iter().map(|p| {
+                format!(
+                    "line {} + line {}: magnitude {}",
+                    p.left + 1,
+                    p.right + 1,
+                    p.magnitude
+                )
+            }));
+            (best[0].left, best[0].right)
         }
-    )
+    };
+    let mut sum = sf_numbers[left].add_unreduced(&sf_numbers[right]);
+    lines.push(format!("line {} + line {}: {:?}", left + 1, right + 1, sum));
+    lines.extend(sum.reduce_traced().iter().map(|step| step.to_string()));
+    lines.join("\n")
 }
 
-fn part1(input: &[SfNumber]) -> u32 {
+fn part1<T: Magnitude>(input: &[T]) -> u32
+where
+    for<'a> &'a T: Add<Output = T>,
+{
     add_sf_list(input).magnitude()
 }
 
-fn part2(input: &[SfNumber]) -> u32 {
-    let mut max = 0;
+fn part2<T: Magnitude + Sync>(input: &[T]) -> u32
+where
+    for<'a> &'a T: Add<Output = T>,
+{
+    best_pairs(input, 1).first().map_or(0, |p| p.magnitude)
+}
 
-    for (i, a) in input.iter().enumerate() {
-        for (j, b) in input.iter().enumerate() {
-            if i != j {
-                let mag = (a + b).magnitude();
-                if mag > max {
-                    max = mag;
-                }
-            }
-        }
+// The sum of two different numbers from a list, identified by their index in it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+struct PairResult {
+    magnitude: u32,
+    left: usize,
+    right: usize,
+}
+
+// Higher magnitude is better; for equal magnitudes the pair appearing first in the list wins,
+// so the result does not depend on how the work was divided between threads.
+impl Ord for PairResult {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.magnitude
+            .cmp(&other.magnitude)
+            .then_with(|| other.left.cmp(&self.left))
+            .then_with(|| other.right.cmp(&self.right))
+    }
+}
+
+impl PartialOrd for PairResult {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+// Find the `k` ordered pairs with the largest sum magnitude, best first.  Rows of the all-pairs
+// grid are shared out between threads, each keeping its own top `k` which are merged at the end.
+// Numbers are tried in order of their value sum, largest first, so once a thread has `k` pairs
+// the rest of a row can be skipped as soon as magnitude_bound says it cannot beat the worst of
+// them.
+fn best_pairs<T: Magnitude + Sync>(input: &[T], k: usize) -> Vec<PairResult>
+where
+    for<'a> &'a T: Add<Output = T>,
+{
+    let k = k.min(input.len().saturating_mul(input.len().saturating_sub(1)));
+    if k == 0 {
+        return vec![];
     }
+    let sums: Vec<u32> = input.iter().map(|n| n.value_sum()).collect();
+    let mut order: Vec<usize> = (0..input.len()).collect();
+    order.sort_by_key(|&i| Reverse(sums[i]));
+    let (sums, order) = (&sums, &order);
+
+    let threads = thread::available_parallelism()
+        .map(|n| n.get())
+        .unwrap_or(1)
+        .min(input.len());
+
+    let mut best: Vec<PairResult> = thread::scope(|scope| {
+        let workers: Vec<_> = (0..threads)
+            .map(|t| {
+                scope.spawn(move || {
+                    let mut top: BinaryHeap<Reverse<PairResult>> = BinaryHeap::with_capacity(k + 1);
+                    for &i in order.iter().skip(t).step_by(threads) {
+                        for &j in order {
+                            if top.len() == k
+                                && magnitude_bound(sums[i] + sums[j])
+                                    < top.peek().unwrap().0.magnitude
+                            {
+                                break; // every later j has a smaller sum
+                            }
+                            if i != j {
+                                top.push(Reverse(PairResult {
+                                    magnitude: (&input[i] + &input[j]).magnitude(),
+                                    left: i,
+                                    right: j,
+                                }));
+                                if top.len() > k {
+                                    top.pop();
+                                }
+                            }
+                        }
+                    }
+                    top.into_iter().map(|r| r.0).collect::<Vec<_>>()
+                })
+            })
+            .collect();
+        workers
+            .into_iter()
+            .flat_map(|w| w.join().unwrap())
+            .collect()
+    });
 
-    max
+    best.sort_unstable_by(|a, b| b.cmp(a));
+    best.truncate(k);
+    best
 }
 
-fn parse_input(input: &str) -> Vec<SfNumber> {
-    input.lines().map(SfNumber::from).collect()
+// Highest magnitude a reduced number with this value sum could have.  Reducing never raises the
+// value sum (explodes add to neighbours or drop off the ends, splits keep it), and a reduced
+// number has values up to 9 at most four pairs deep, so it is at best the full depth-4 tree with
+// 9s filled in from the heaviest (leftmost) positions.
+fn magnitude_bound(value_sum: u32) -> u32 {
+    // weights 3^l * 2^r of the 16 deepest positions, with l + r = 4, heaviest first
+    const WEIGHTS: [(u32, u32); 5] = [(81, 1), (54, 4), (36, 6), (24, 4), (16, 1)];
+    let mut left = value_sum;
+    let mut bound = 0;
+    for (weight, count) in WEIGHTS {
+        let values = left.min(9 * count);
+        bound += values * weight;
+        left -= values;
+    }
+    bound
+}
+
+fn parse_input<T: for<'a> From<&'a str>>(input: &str) -> Vec<T> {
+    input.lines().map(T::from).collect()
 }
 
-fn add_sf_list(list: &[SfNumber]) -> SfNumber {
+fn add_sf_list<T>(list: &[T]) -> T
+where
+    for<'a> &'a T: Add<Output = T>,
+{
     // why not able to use an iterator?
     // *list.iter().reduce(|acc, sf_num| &(acc + sf_num)).unwrap()
     // Error[E0515]: cannot return reference to temporary value
 
-    let mut acc: SfNumber = &list[0] + &list[1];
+    let mut acc: T = &list[0] + &list[1];
     if list.len() > 2 {
         for num in list.iter().skip(2) {
             acc = &acc + num;
@@ -52,13 +184,174 @@ fn add_sf_list(list: &[SfNumber]) -> SfNumber {
     acc
 }
 
+trait Magnitude {
+    fn magnitude(&self) -> u32;
+    // sum of all regular numbers
+    fn value_sum(&self) -> u32;
+}
+
+#[derive(Debug, PartialEq)]
+enum SfError {
+    UnexpectedChar {
+        pos: usize,
+        found: char,
+        expected: &'static str,
+    },
+    UnexpectedEnd {
+        pos: usize,
+        expected: &'static str,
+    },
+    MultiDigit {
+        pos: usize,
+    },
+    TrailingInput {
+        pos: usize,
+    },
+    TooDeep {
+        pos: usize,
+    },
+}
+
+impl fmt::Display for SfError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            SfError::UnexpectedChar {
+                pos,
+                found,
+                expected,
+            } => write!(
+                f,
+                "column {}: expected {}, found {:?}",
+                pos + 1,
+                expected,
+                found
+            ),
+            SfError::UnexpectedEnd { pos, expected } => {
+                write!(
+                    f,
+                    "column {}: expected {}, found end of input",
+                    pos + 1,
+                    expected
+                )
+            }
+            SfError::MultiDigit { pos } => {
+                write!(
+                    f,
+                    "column {}: regular numbers must be a single digit",
+                    pos + 1
+                )
+            }
+            SfError::TrailingInput { pos } => {
+                write!(f, "column {}: unexpected input after number", pos + 1)
+            }
+            SfError::TooDeep { pos } => {
+                write!(
+                    f,
+                    "column {}: pair is nested inside more than 4 pairs",
+                    pos + 1
+                )
+            }
+        }
+    }
+}
+
+// Check that a string is a well formed snailfish number: every pair is "[" element "," element
+// "]", and every regular number is a single digit, as in the puzzle input.  The input is already
+// reduced, so no pair may be nested inside more than 4 pairs: it would have exploded.
+fn validate(s: &str) -> Result<(), SfError> {
+    fn element(s: &[char], pos: &mut usize, depth: usize) -> Result<(), SfError> {
+        match s.get(*pos) {
+            Some('[') if depth == 4 => Err(SfError::TooDeep { pos: *pos }),
+            Some('[') => {
+                *pos += 1;
+                element(s, pos, depth + 1)?;
+                expect(s, pos, ',', "','")?;
+                element(s, pos, depth + 1)?;
+                expect(s, pos, ']', "']'")
+            }
+            Some(c) if c.is_ascii_digit() => {
+                *pos += 1;
+                if s.get(*pos).is_some_and(|c| c.is_ascii_digit()) {
+                    return Err(SfError::MultiDigit { pos: *pos - 1 });
+                }
+                Ok(())
+            }
+            Some(&found) => Err(SfError::UnexpectedChar {
+                pos: *pos,
+                found,
+                expected: "'[' or a digit",
+            }),
+            None => Err(SfError::UnexpectedEnd {
+                pos: *pos,
+                expected: "'[' or a digit",
+            }),
+        }
+    }
+
+    fn expect(s: &[char], pos: &mut usize, c: char, expected: &'static str) -> Result<(), SfError> {
+        match s.get(*pos) {
+            Some(&found) if found == c => {
+                *pos += 1;
+                Ok(())
+            }
+            Some(&found) => Err(SfError::UnexpectedChar {
+                pos: *pos,
+                found,
+                expected,
+            }),
+            None => Err(SfError::UnexpectedEnd {
+                pos: *pos,
+                expected,
+            }),
+        }
+    }
+
+    let chars: Vec<char> = s.chars().collect();
+    let mut pos = 0;
+    // the number itself must be a pair, not a lone regular number
+    if chars.first().is_some_and(|c| c.is_ascii_digit()) {
+        expect(&chars, &mut pos, '[', "'['")?;
+    }
+    element(&chars, &mut pos, 0)?;
+    if pos < chars.len() {
+        return Err(SfError::TrailingInput { pos });
+    }
+    Ok(())
+}
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Action {
+    Explode(u32, u32),
+    Split(u32),
+}
+
+// One step of a traced reduction: what was done, and the number afterwards
+struct TraceStep {
+    action: Action,
+    after: String,
+}
+
+impl fmt::Display for TraceStep {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.action {
+            Action::Explode(l, r) => write!(f, "after explode [{},{}]: ", l, r)?,
+            Action::Split(v) => write!(f, "after split {}: ", v)?,
+        }
+        write!(f, "{}", self.after)
+    }
+}
+
 // An earlier attempt of this tried using an actual tree data structure, but being able to do the
 // explode operation, which requires mutating far parts of the tree proved too difficult (for me)
 // in rust.
 //
 // Instead, the number is stored as a vector of tokens from the string.  This allows for much
 // simpler in-place modification during explode().
+//
+// run() now uses SfTree below; this version is only built for the tests and the benchmark, as a
+// reference to check it against.
 
+#[cfg(test)]
 #[derive(Clone, Copy, PartialEq)]
 enum Token {
     LeftBracket,
@@ -67,6 +360,7 @@ enum Token {
     Number(u32),
 }
 
+#[cfg(test)]
 impl Token {
     fn number(&self) -> Option<u32> {
         if let Token::Number(num) = self {
@@ -77,6 +371,7 @@ impl Token {
     }
 }
 
+#[cfg(test)]
 impl From<char> for Token {
     fn from(c: char) -> Self {
         match c {
@@ -89,6 +384,7 @@ impl From<char> for Token {
     }
 }
 
+#[cfg(test)]
 impl fmt::Debug for Token {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
@@ -104,18 +400,24 @@ impl fmt::Debug for Token {
     }
 }
 
+#[cfg(test)]
 struct SfNumber {
     n: Vec<Token>,
 }
 
+#[cfg(test)]
 impl From<&str> for SfNumber {
     fn from(s: &str) -> Self {
+        if let Err(e) = validate(s) {
+            panic!("Invalid snailfish number {:?}: {}", s, e);
+        }
         Self {
             n: s.chars().map(Token::from).collect(),
         }
     }
 }
 
+#[cfg(test)]
 impl Add for &SfNumber {
     type Output = SfNumber;
 
@@ -132,6 +434,7 @@ impl Add for &SfNumber {
     }
 }
 
+#[cfg(test)]
 impl SfNumber {
     fn reduce(&mut self) {
         // println!("REDUCING:      {:?}", self);
@@ -217,7 +520,7 @@ impl SfNumber {
                     Token::LeftBracket,
                     Token::Number(v / 2),
                     Token::Comma,
-                    Token::Number((v + 1) / 2),
+                    Token::Number(v.div_ceil(2)),
                     Token::RightBracket,
                 ],
             );
@@ -225,12 +528,20 @@ impl SfNumber {
         }
         false
     }
+}
 
+#[cfg(test)]
+impl Magnitude for SfNumber {
     fn magnitude(&self) -> u32 {
         token_magnitude(&self.n)
     }
+
+    fn value_sum(&self) -> u32 {
+        self.n.iter().filter_map(|t| t.number()).sum()
+    }
 }
 
+#[cfg(test)]
 fn find_comma(tokens: &[Token]) -> usize {
     let mut depth = 0;
     for (i, c) in tokens.iter().enumerate() {
@@ -248,6 +559,7 @@ fn find_comma(tokens: &[Token]) -> usize {
     unreachable!("Unable to find comma in {:?}", tokens);
 }
 
+#[cfg(test)]
 fn token_magnitude(tokens: &[Token]) -> u32 {
     assert_eq!(Token::LeftBracket, tokens[0]);
     assert_eq!(Token::RightBracket, tokens[tokens.len() - 1]);
@@ -266,6 +578,7 @@ fn token_magnitude(tokens: &[Token]) -> u32 {
     left * 3 + right * 2
 }
 
+#[cfg(test)]
 impl fmt::Debug for SfNumber {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         write!(
@@ -279,6 +592,336 @@ impl fmt::Debug for SfNumber {
     }
 }
 
+// Arena-backed binary tree.  Nodes refer to their parent and children by index into `slots`, and
+// the leaves are also threaded into an in-order linked list, so the neighbours that an explode
+// adds to are one hop away and finding the next explode or split never restarts from the root.
+
+#[derive(Clone, Copy)]
+enum Node {
+    Leaf(u32),
+    Pair(usize, usize),
+}
+
+#[derive(Clone)]
+struct Slot {
+    node: Node,
+    parent: Option<usize>,
+    depth: usize,
+    // in-order leaf links (only meaningful for leaves)
+    prev: Option<usize>,
+    next: Option<usize>,
+}
+
+#[derive(Clone, Default)]
+struct SfTree {
+    slots: Vec<Slot>,
+    free: Vec<usize>,
+    root: usize,
+    first: Option<usize>,
+    last: Option<usize>,
+}
+
+impl From<&str> for SfTree {
+    fn from(s: &str) -> Self {
+        if let Err(e) = validate(s) {
+            panic!("Invalid snailfish number {:?}: {}", s, e);
+        }
+        let mut tree = SfTree::default();
+        let mut pos = 0;
+        tree.root = tree.parse_node(s.as_bytes(), &mut pos, None, 0);
+        tree
+    }
+}
+
+impl Add for &SfTree {
+    type Output = SfTree;
+
+    fn add(self, other: Self) -> SfTree {
+        let mut new = self.add_unreduced(other);
+        new.reduce();
+        new
+    }
+}
+
+impl SfTree {
+    // number of live nodes
+    fn len(&self) -> usize {
+        self.slots.len() - self.free.len()
+    }
+
+    fn alloc(&mut self, node: Node, parent: Option<usize>, depth: usize) -> usize {
+        let slot = Slot {
+            node,
+            parent,
+            depth,
+            prev: None,
+            next: None,
+        };
+        if let Some(id) = self.free.pop() {
+            self.slots[id] = slot;
+            id
+        } else {
+            self.slots.push(slot);
+            self.slots.len() - 1
+        }
+    }
+
+    // allocate a leaf and link it after the current last leaf; nodes must be built in order
+    fn push_leaf(&mut self, value: u32, parent: Option<usize>, depth: usize) -> usize {
+        let id = self.alloc(Node::Leaf(value), parent, depth);
+        self.slots[id].prev = self.last;
+        match self.last {
+            Some(last) => self.slots[last].next = Some(id),
+            None => self.first = Some(id),
+        }
+        self.last = Some(id);
+        id
+    }
+
+    // parse a number that has already passed validate()
+    fn parse_node(
+        &mut self,
+        s: &[u8],
+        pos: &mut usize,
+        parent: Option<usize>,
+        depth: usize,
+    ) -> usize {
+        match s[*pos] {
+            b'[' => {
+                let id = self.alloc(Node::Pair(0, 0), parent, depth);
+                *pos += 1;
+                let left = self.parse_node(s, pos, Some(id), depth + 1);
+                *pos += 1; // ','
+                let right = self.parse_node(s, pos, Some(id), depth + 1);
+                *pos += 1; // ']'
+                self.slots[id].node = Node::Pair(left, right);
+                id
+            }
+            c => {
+                *pos += 1;
+                self.push_leaf((c - b'0') as u32, parent, depth)
+            }
+        }
+    }
+
+    // copy the subtree at `id` in `from` into self, shifting depths to suit the new parent
+    fn copy_node(
+        &mut self,
+        from: &SfTree,
+        id: usize,
+        parent: Option<usize>,
+        depth: usize,
+    ) -> usize {
+        match from.slots[id].node {
+            Node::Leaf(value) => self.push_leaf(value, parent, depth),
+            Node::Pair(left, right) => {
+                let new = self.alloc(Node::Pair(0, 0), parent, depth);
+                let left = self.copy_node(from, left, Some(new), depth + 1);
+                let right = self.copy_node(from, right, Some(new), depth + 1);
+                self.slots[new].node = Node::Pair(left, right);
+                new
+            }
+        }
+    }
+
+    fn value(&self, id: usize) -> Option<u32> {
+        match self.slots[id].node {
+            Node::Leaf(value) => Some(value),
+            Node::Pair(..) => None,
+        }
+    }
+
+    fn add_to_leaf(&mut self, id: usize, v: u32) {
+        if let Node::Leaf(value) = self.slots[id].node {
+            self.slots[id].node = Node::Leaf(value + v);
+        }
+    }
+
+    // Same result as SfNumber::reduce() (explode the leftmost deep pair, otherwise split the
+    // leftmost large number, repeat), but done in two walks along the leaf list.
+    fn reduce(&mut self) {
+        self.reduce_with(|_, _| ());
+    }
+
+    // Reduce, returning every intermediate number along with the action that produced it
+    fn reduce_traced(&mut self) -> Vec<TraceStep> {
+        let mut trace = vec![];
+        self.reduce_with(|action, tree| {
+            trace.push(TraceStep {
+                action,
+                after: format!("{:?}", tree),
+            })
+        });
+        trace
+    }
+
+    // Add without reducing (eg. so the sum can be passed to reduce_traced())
+    fn add_unreduced(&self, other: &SfTree) -> SfTree {
+        let mut new = SfTree {
+            slots: Vec::with_capacity(self.len() + other.len() + 1),
+            ..Default::default()
+        };
+        new.root = new.alloc(Node::Pair(0, 0), None, 0);
+        let left = new.copy_node(self, self.root, Some(new.root), 1);
+        let right = new.copy_node(other, other.root, Some(new.root), 1);
+        new.slots[new.root].node = Node::Pair(left, right);
+        new
+    }
+
+    // The actions happen in the same order as SfNumber::reduce() would do them, and `observe`
+    // is called after each one.
+    fn reduce_with<F: FnMut(Action, &SfTree)>(&mut self, mut observe: F) {
+        // Exploding never makes anything deeper, so every pair that needs to explode can be
+        // handled in a single left to right pass.
+        let mut cur = self.first;
+        while let Some(leaf) = cur {
+            if self.slots[leaf].depth > 4 {
+                let pair = self.slots[leaf].parent.unwrap();
+                let action = self.explode(pair);
+                observe(action, self);
+                cur = self.slots[pair].next;
+            } else {
+                cur = self.slots[leaf].next;
+            }
+        }
+
+        // Everything left of `cur` is now below 10.  A split pair at depth 4 explodes straight
+        // away, which may push the leaf before it over 9 again, so step back to that.
+        let mut cur = self.first;
+        while let Some(leaf) = cur {
+            let v = self.value(leaf).unwrap();
+            if v > 9 {
+                let (left, _) = self.split(leaf);
+                observe(Action::Split(v), self);
+                if self.slots[leaf].depth >= 4 {
+                    let prev = self.slots[left].prev;
+                    let action = self.explode(leaf);
+                    observe(action, self);
+                    cur = match prev {
+                        Some(prev) if self.value(prev).unwrap() > 9 => Some(prev),
+                        _ => self.slots[leaf].next,
+                    };
+                } else {
+                    cur = Some(left);
+                }
+            } else {
+                cur = self.slots[leaf].next;
+            }
+        }
+    }
+
+    // replace a pair of two leaves with a 0, adding its values to the neighbouring leaves
+    fn explode(&mut self, pair: usize) -> Action {
+        let (left, right) = match self.slots[pair].node {
+            Node::Pair(left, right) => (left, right),
+            Node::Leaf(_) => unreachable!("Exploding a leaf"),
+        };
+        let lv = self
+            .value(left)
+            .expect("Exploding pair must contain numbers");
+        let rv = self
+            .value(right)
+            .expect("Exploding pair must contain numbers");
+        let prev = self.slots[left].prev;
+        let next = self.slots[right].next;
+
+        if let Some(prev) = prev {
+            self.add_to_leaf(prev, lv);
+            self.slots[prev].next = Some(pair);
+        } else {
+            self.first = Some(pair);
+        }
+        if let Some(next) = next {
+            self.add_to_leaf(next, rv);
+            self.slots[next].prev = Some(pair);
+        } else {
+            self.last = Some(pair);
+        }
+
+        let slot = &mut self.slots[pair];
+        slot.node = Node::Leaf(0);
+        slot.prev = prev;
+        slot.next = next;
+        self.free.push(left);
+        self.free.push(right);
+        Action::Explode(lv, rv)
+    }
+
+    // replace a leaf with a pair of its value halved, returning the new leaves
+    fn split(&mut self, leaf: usize) -> (usize, usize) {
+        let v = self.value(leaf).unwrap();
+        let depth = self.slots[leaf].depth + 1;
+        let prev = self.slots[leaf].prev;
+        let next = self.slots[leaf].next;
+
+        let left = self.alloc(Node::Leaf(v / 2), Some(leaf), depth);
+        let right = self.alloc(Node::Leaf(v.div_ceil(2)), Some(leaf), depth);
+        self.slots[left].prev = prev;
+        self.slots[left].next = Some(right);
+        self.slots[right].prev = Some(left);
+        self.slots[right].next = next;
+        match prev {
+            Some(prev) => self.slots[prev].next = Some(left),
+            None => self.first = Some(left),
+        }
+        match next {
+            Some(next) => self.slots[next].prev = Some(right),
+            None => self.last = Some(right),
+        }
+
+        let slot = &mut self.slots[leaf];
+        slot.node = Node::Pair(left, right);
+        slot.prev = None;
+        slot.next = None;
+        (left, right)
+    }
+
+    fn node_magnitude(&self, id: usize) -> u32 {
+        match self.slots[id].node {
+            Node::Leaf(value) => value,
+            Node::Pair(left, right) => {
+                self.node_magnitude(left) * 3 + self.node_magnitude(right) * 2
+            }
+        }
+    }
+
+    fn node_value_sum(&self, id: usize) -> u32 {
+        match self.slots[id].node {
+            Node::Leaf(value) => value,
+            Node::Pair(left, right) => self.node_value_sum(left) + self.node_value_sum(right),
+        }
+    }
+
+    fn fmt_node(&self, id: usize, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.slots[id].node {
+            Node::Leaf(value) => write!(f, "{}", value),
+            Node::Pair(left, right) => {
+                write!(f, "[")?;
+                self.fmt_node(left, f)?;
+                write!(f, ",")?;
+                self.fmt_node(right, f)?;
+                write!(f, "]")
+            }
+        }
+    }
+}
+
+impl Magnitude for SfTree {
+    fn magnitude(&self) -> u32 {
+        self.node_magnitude(self.root)
+    }
+
+    fn value_sum(&self) -> u32 {
+        self.node_value_sum(self.root)
+    }
+}
+
+impl fmt::Debug for SfTree {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.fmt_node(self.root, f)
+    }
+}
+
 #[test]
 fn test() {
     let test_input = "\
@@ -290,7 +933,7 @@ fn test() {
 [[[9,[3,8]],[[0,9],6]],[[[3,7],[4,9]],3]]
 [[[[1,3],[5,3]],[[1,3],[8,7]]],[[[4,9],[6,9]],[[8,2],[7,3]]]]
 ";
-    let numbers = parse_input(test_input);
+    let numbers = parse_input::<SfNumber>(test_input);
     assert_eq!(7, numbers.len());
     assert_eq!(
         test_input,
@@ -323,7 +966,7 @@ fn test() {
 ";
     assert_eq!(
         "[[[[1,1],[2,2]],[3,3]],[4,4]]",
-        format!("{:?}", add_sf_list(&parse_input(test_input)))
+        format!("{:?}", add_sf_list(&parse_input::<SfNumber>(test_input)))
     );
 
     let test_input = "\
@@ -335,7 +978,7 @@ fn test() {
 ";
     assert_eq!(
         "[[[[3,0],[5,3]],[4,4]],[5,5]]",
-        format!("{:?}", add_sf_list(&parse_input(test_input)))
+        format!("{:?}", add_sf_list(&parse_input::<SfNumber>(test_input)))
     );
 
     let test_input = "\
@@ -348,7 +991,7 @@ fn test() {
 ";
     assert_eq!(
         "[[[[5,0],[7,4]],[5,5]],[6,6]]",
-        format!("{:?}", add_sf_list(&parse_input(test_input)))
+        format!("{:?}", add_sf_list(&parse_input::<SfNumber>(test_input)))
     );
 
     let test_input = "\
@@ -365,7 +1008,7 @@ fn test() {
 ";
     assert_eq!(
         "[[[[8,7],[7,7]],[[8,6],[7,7]]],[[[0,7],[6,6]],[8,7]]]",
-        format!("{:?}", add_sf_list(&parse_input(test_input)))
+        format!("{:?}", add_sf_list(&parse_input::<SfNumber>(test_input)))
     );
 
     assert_eq!(143, SfNumber::from("[[1,2],[[3,4],5]]").magnitude());
@@ -402,7 +1045,230 @@ fn test() {
 [[2,[[7,7],7]],[[5,8],[[9,3],[0,2]]]]
 [[[[5,2],5],[8,[3,7]]],[[5,[7,5]],[4,4]]]
 ";
-    assert_eq!(4140, part1(&parse_input(test_input)));
+    assert_eq!(4140, part1(&parse_input::<SfNumber>(test_input)));
+
+    assert_eq!(3993, part2(&parse_input::<SfNumber>(test_input)));
+}
+
+#[test]
+fn test_tree() {
+    for s in [
+        "[[[[1,2],[3,4]],[[5,6],[7,8]]],9]",
+        "[[[9,[3,8]],[[0,9],6]],[[[3,7],[4,9]],3]]",
+    ] {
+        assert_eq!(s, format!("{:?}", SfTree::from(s)));
+    }
+    assert_eq!(
+        "[[[[0,7],4],[[7,8],[6,0]]],[8,1]]",
+        format!(
+            "{:?}",
+            &SfTree::from("[[[[4,3],4],4],[7,[[8,4],9]]]") + &SfTree::from("[1,1]")
+        )
+    );
+    assert_eq!(
+        3488,
+        SfTree::from("[[[[8,7],[7,7]],[[8,6],[7,7]]],[[[0,7],[6,6]],[8,7]]]").magnitude()
+    );
+
+    let test_input = "\
+[[[0,[5,8]],[[1,7],[9,6]]],[[4,[1,2]],[[1,4],2]]]
+[[[5,[2,8]],4],[5,[[9,9],0]]]
+[6,[[[6,2],[5,6]],[[7,6],[4,7]]]]
+[[[6,[0,7]],[0,9]],[4,[9,[9,0]]]]
+[[[7,[6,4]],[3,[1,3]]],[[[5,5],1],9]]
+[[6,[[7,3],[3,2]]],[[[3,8],[5,7]],4]]
+[[[[5,4],[7,7]],8],[[8,3],8]]
+[[9,3],[[9,9],[6,[4,9]]]]
+[[2,[[7,7],7]],[[5,8],[[9,3],[0,2]]]]
+[[[[5,2],5],[8,[3,7]]],[[5,[7,5]],[4,4]]]
+";
+    let trees = parse_input::<SfTree>(test_input);
+    assert_eq!(4140, part1(&trees));
+    assert_eq!(3993, part2(&trees));
+    // no pairs to add
+    assert_eq!(0, part2(&parse_input::<SfTree>("[1,2]\n")));
+    assert_eq!(
+        vec![
+            PairResult {
+                magnitude: 3993,
+                left: 8,
+                right: 0
+            },
+            PairResult {
+                magnitude: 3937,
+                left: 8,
+                right: 6
+            }
+        ],
+        best_pairs(&trees, 2)
+    );
+    let mut all = vec![];
+    for (i, a) in trees.iter().enumerate() {
+        for (j, b) in trees.iter().enumerate() {
+            if i != j {
+                all.push((a + b).magnitude());
+            }
+        }
+    }
+    all.sort_unstable_by(|a, b| b.cmp(a));
+    assert_eq!(
+        all,
+        best_pairs(&trees, 1000)
+            .iter()
+            .map(|p| p.magnitude)
+            .collect::<Vec<u32>>()
+    );
+    assert_eq!(all.len(), best_pairs(&trees, usize::MAX).len());
+    assert!(best_pairs(&trees, 0).is_empty());
+    // the pruning bound holds for every pair, and is exact for the heaviest possible number
+    for a in &trees {
+        for b in &trees {
+            assert!((a + b).magnitude() <= magnitude_bound(a.value_sum() + b.value_sum()));
+        }
+    }
+    let full = SfTree::from("[[[[9,9],[9,9]],[[9,9],[9,9]]],[[[9,9],[9,9]],[[9,9],[9,9]]]]");
+    assert_eq!(full.magnitude(), magnitude_bound(full.value_sum()));
+    assert_eq!(full.magnitude(), magnitude_bound(1000));
+
+    // pruned search agrees with trying every pair
+    for seed in 1..5 {
+        let trees = parse_input::<SfTree>(&random_sf_list(30, seed));
+        let mut all = vec![];
+        for (i, a) in trees.iter().enumerate() {
+            for (j, b) in trees.iter().enumerate() {
+                if i != j {
+                    all.push((a + b).magnitude());
+                }
+            }
+        }
+        all.sort_unstable_by(|a, b| b.cmp(a));
+        all.truncate(10);
+        let best: Vec<u32> = best_pairs(&trees, 10).iter().map(|p| p.magnitude).collect();
+        assert_eq!(all, best);
+    }
+
+    // every pairwise sum must match the token implementation exactly
+    let numbers = parse_input::<SfNumber>(test_input);
+    for (a, b) in trees.iter().zip(numbers.iter()) {
+        for (c, d) in trees.iter().zip(numbers.iter()) {
+            assert_eq!(format!("{:?}", b + d), format!("{:?}", a + c));
+        }
+    }
+}
+
+#[test]
+fn test_validate_and_trace() {
+    assert_eq!(Ok(()), validate("[[1,2],[[3,4],5]]"));
+    assert_eq!(
+        Err(SfError::UnexpectedEnd {
+            pos: 12,
+            expected: "']'"
+        }),
+        validate("[[1,2],[3,4]")
+    );
+    assert_eq!(
+        Err(SfError::UnexpectedChar {
+            pos: 4,
+            found: ',',
+            expected: "']'"
+        }),
+        validate("[1,2,3]")
+    );
+    assert_eq!(
+        Err(SfError::UnexpectedChar {
+            pos: 3,
+            found: ']',
+            expected: "','"
+        }),
+        validate("[[1],2]")
+    );
+    assert_eq!(Err(SfError::MultiDigit { pos: 3 }), validate("[1,10]"));
+    assert_eq!(Err(SfError::TrailingInput { pos: 5 }), validate("[1,2]]"));
+    assert_eq!(Ok(()), validate("[[[[1,2],3],4],5]"));
+    assert_eq!(
+        Err(SfError::TooDeep { pos: 4 }),
+        validate("[[[[[1,[2,3]],4],5],6],7]")
+    );
+    assert_eq!(
+        Err(SfError::UnexpectedChar {
+            pos: 0,
+            found: '7',
+            expected: "'['"
+        }),
+        validate("7")
+    );
+    assert_eq!(
+        "column 4: regular numbers must be a single digit",
+        SfError::MultiDigit { pos: 3 }.to_string()
+    );
+
+    let mut sum =
+        SfTree::from("[[[[4,3],4],4],[7,[[8,4],9]]]").add_unreduced(&SfTree::from("[1,1]"));
+    assert_eq!(
+        "[[[[[4,3],4],4],[7,[[8,4],9]]],[1,1]]",
+        format!("{:?}", sum)
+    );
+    assert_eq!(
+        vec![
+            "after explode [4,3]: [[[[0,7],4],[7,[[8,4],9]]],[1,1]]",
+            "after explode [8,4]: [[[[0,7],4],[15,[0,13]]],[1,1]]",
+            "after split 15: [[[[0,7],4],[[7,8],[0,13]]],[1,1]]",
+            "after split 13: [[[[0,7],4],[[7,8],[0,[6,7]]]],[1,1]]",
+            "after explode [6,7]: [[[[0,7],4],[[7,8],[6,0]]],[8,1]]",
+        ],
+        sum.reduce_traced()
+            .iter()
+            .map(|step| step.to_string())
+            .collect::<Vec<String>>()
+    );
+}
+
+// Build a list of random reduced snailfish numbers, for tests and benchmarking
+#[cfg(test)]
+fn random_sf_list(count: usize, seed: u64) -> String {
+    fn random_sf(s: &mut String, depth: usize, rng: &mut Rng) {
+        let r = rng.next();
+        if depth > 0 && (depth == 4 || r.is_multiple_of(3)) {
+            s.push_str(&format!("{}", r % 10));
+        } else {
+            s.push('[');
+            random_sf(s, depth + 1, rng);
+            s.push(',');
+            random_sf(s, depth + 1, rng);
+            s.push(']');
+        }
+    }
 
-    assert_eq!(3993, part2(&parse_input(test_input)));
+    let mut rng = Rng::new(seed);
+    let mut s = String::new();
+    for _ in 0..count {
+        random_sf(&mut s, 0, &mut rng);
+        s.push('\n');
+    }
+    s
+}
+
+// cargo test --release bench_part2 -- --ignored --nocapture
+#[test]
+#[ignore]
+fn bench_part2() {
+    use std::time::Instant;
+
+    let input = random_sf_list(300, 0x2021_1218);
+
+    let t0 = Instant::now();
+    let tree_max = part2(&parse_input::<SfTree>(&input));
+    let tree_time = t0.elapsed().as_secs_f64();
+
+    let t0 = Instant::now();
+    let token_max = part2(&parse_input::<SfNumber>(&input));
+    let token_time = t0.elapsed().as_secs_f64();
+
+    println!(
+        "part2 on 300 numbers: tokens {:.3}s, tree {:.3}s ({:.1}x)",
+        token_time,
+        tree_time,
+        token_time / tree_time
+    );
+    assert_eq!(token_max, tree_max);
 }
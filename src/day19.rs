@@ -1,23 +1,29 @@
+#[cfg(test)]
+use crate::rng::Rng;
 use crate::Part;
 use std::cell::RefCell;
+use std::cmp::Ordering;
 use std::collections::HashSet;
 use std::collections::VecDeque;
 use std::fmt;
 
-thread_local!(static RESULT: RefCell<Option<(HashSet<Pos>, Vec<Pos>)>> = RefCell::new(None));
+thread_local!(static RESULT: RefCell<Option<BeaconMap>> = const { RefCell::new(None) });
 
 pub fn run(input: &str, part: Part) -> String {
     RESULT.with(|result| {
         if result.borrow().is_none() {
             let scans = parse_input(input);
-            *result.borrow_mut() = Some(search(&scans));
+            match search(&scans, &AlignOptions::default()) {
+                Ok(found) => *result.borrow_mut() = Some(found),
+                Err(e) => return format!("{}", e),
+            }
         }
         if let Some(result) = &*result.borrow() {
             format!(
                 "{}",
                 match part {
-                    Part::One => result.0.len(),
-                    Part::Two => max_distance(&result.1),
+                    Part::One => result.beacons.len(),
+                    Part::Two => max_distance(&result.scanner_positions()),
                 }
             )
         } else {
@@ -26,6 +32,18 @@ pub fn run(input: &str, part: Part) -> String {
     })
 }
 
+// The aligned beacon map as json, or as a ply or xyz point cloud, for `export`
+pub fn export(input: &str, format: &str) -> Result<String, String> {
+    let to_text: fn(&BeaconMap) -> String = match format {
+        "json" => BeaconMap::to_json,
+        "ply" => BeaconMap::to_ply,
+        "xyz" => BeaconMap::to_xyz,
+        _ => return Err("unknown file type".to_string()),
+    };
+    let map = search(&parse_input(input), &AlignOptions::default()).map_err(|e| e.to_string())?;
+    Ok(to_text(&map))
+}
+
 #[derive(Clone, Copy, Eq, Hash, PartialEq)]
 struct Pos {
     x: i32,
@@ -69,6 +87,30 @@ impl Pos {
         }
     }
 
+    // as rotate(), but orientations 24..48 are the same rotations applied to the mirror image
+    // (x negated) of the position
+    fn orient(&self, o: i32) -> Pos {
+        if o < 24 {
+            self.rotate(o)
+        } else {
+            Pos::new(-self.x, self.y, self.z).rotate(o - 24)
+        }
+    }
+
+    // the matrix M where M * p == p.orient(o)
+    fn orientation_matrix(o: i32) -> [[i32; 3]; 3] {
+        let columns = [
+            Pos::new(1, 0, 0).orient(o),
+            Pos::new(0, 1, 0).orient(o),
+            Pos::new(0, 0, 1).orient(o),
+        ];
+        [
+            [columns[0].x, columns[1].x, columns[2].x],
+            [columns[0].y, columns[1].y, columns[2].y],
+            [columns[0].z, columns[1].z, columns[2].z],
+        ]
+    }
+
     fn offset(&self, by: Pos) -> Pos {
         Pos::new(self.x + by.x, self.y + by.y, self.z + by.z)
     }
@@ -159,69 +201,279 @@ impl fmt::Debug for RotatedBeacons {
     }
 }
 
-// returns (set of beacons, vec of scanner positions)
-fn search(scans: &[Scan]) -> (HashSet<Pos>, Vec<Pos>) {
-    // everything will be relative to scan[0], so load its beacons into the
-    // map straight away
-    let mut beacons = HashSet::new();
-    let mut scanners: Vec<Pos> = scans.iter().map(|_| Pos::new(0, 0, 0)).collect();
-    for beacon in &scans[0].beacons {
-        beacons.insert(*beacon);
-    }
-
-    // queue of (scan_number, rot, rotated_beacons) for each scan/rotation pair
-    let mut queue: VecDeque<RotatedBeacons> = VecDeque::new();
-    for scan in scans.iter().skip(1) {
-        for rot in 0..24 {
-            queue.push_back(RotatedBeacons {
-                scan_number: scan.number,
-                rotation: rot,
-                beacons: scan.beacons.iter().map(|p| p.rotate(rot)).collect(),
-            });
+struct AlignOptions {
+    // number of beacons two scans must have in common to be aligned
+    overlap: usize,
+    // also try mirror images of each scan (48 orientations instead of 24)
+    reflections: bool,
+    // only try aligning scans whose beacon distance fingerprints have enough in common
+    fingerprints: bool,
+}
+
+impl Default for AlignOptions {
+    fn default() -> Self {
+        AlignOptions {
+            overlap: 12,
+            reflections: false,
+            fingerprints: true,
         }
     }
+}
 
-    // queue of known sets of points (re-orientated)
-    let mut known: Vec<HashSet<Pos>> =
-        vec![HashSet::from_iter(scans[0].beacons.clone().into_iter())];
-
-    while let Some(rotated_beacons) = queue.pop_front() {
-        let mut matched = false;
-        // println!(
-        //     "Trying to match {:?}, {} unknown sets, {} known sets, {} known beacons",
-        //     rotated_beacons,
-        //     queue.len() + 1,
-        //     known.len(),
-        //     beacons.len()
-        // );
-        for k in known.iter() {
-            if let Some(scanner_pos) = match_beacons(&rotated_beacons, k) {
-                // println!("MATCHED!  {:?} is at {:?}", rotated_beacons, scanner_pos);
-                matched = true;
-                // merge everything in this match into the set of known beacons
-                let set: HashSet<Pos> = HashSet::from_iter(
-                    rotated_beacons
-                        .beacons
-                        .iter()
-                        .map(|p| p.offset(scanner_pos)),
-                );
-                beacons.extend(&set);
-                // store this set for later comparisons
-                known.push(set);
-                // record the scanner's position for part 2
-                scanners[rotated_beacons.scan_number] = scanner_pos;
-                // remove other rotations of this scan from the queue
-                queue.retain(|rb| rb.scan_number != rotated_beacons.scan_number);
-                break;
+impl AlignOptions {
+    fn orientations(&self) -> i32 {
+        if self.reflections {
+            48
+        } else {
+            24
+        }
+    }
+}
+
+#[derive(Debug, PartialEq)]
+enum AlignError {
+    // these scans do not overlap enough with anything that could be aligned
+    Unaligned(Vec<usize>),
+    // every alignment would need at least one beacon in common
+    ZeroOverlap,
+}
+
+impl fmt::Display for AlignError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AlignError::Unaligned(scans) => write!(
+                f,
+                "Unable to align scanners {}",
+                scans
+                    .iter()
+                    .map(|n| n.to_string())
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+            AlignError::ZeroOverlap => write!(f, "Overlap must be at least 1 beacon"),
+        }
+    }
+}
+
+// Rotation and reflection invariant summary of a scan: the squared distance between every pair
+// of its beacons, sorted.
+fn fingerprint(scan: &Scan) -> Vec<i64> {
+    let mut distances = Vec::with_capacity(scan.beacons.len() * scan.beacons.len() / 2);
+    for (i, a) in scan.beacons.iter().enumerate() {
+        for b in scan.beacons.iter().skip(i + 1) {
+            let (dx, dy, dz) = ((a.x - b.x) as i64, (a.y - b.y) as i64, (a.z - b.z) as i64);
+            distances.push(dx * dx + dy * dy + dz * dz);
+        }
+    }
+    distances.sort_unstable();
+    distances
+}
+
+// number of distances two fingerprints have in common
+fn common_distances(a: &[i64], b: &[i64]) -> usize {
+    let (mut i, mut j, mut count) = (0, 0, 0);
+    while i < a.len() && j < b.len() {
+        match a[i].cmp(&b[j]) {
+            Ordering::Less => i += 1,
+            Ordering::Greater => j += 1,
+            Ordering::Equal => {
+                count += 1;
+                i += 1;
+                j += 1;
             }
         }
-        if !matched {
-            // try this one later
-            queue.push_back(rotated_beacons);
+    }
+    count
+}
+
+// How one scanner's readings were fitted into scanner 0's frame of reference
+#[derive(Clone, Debug, PartialEq)]
+struct ScannerAlignment {
+    scan_number: usize,
+    orientation: i32,
+    // maps the scanner's own coordinates onto scanner 0's axes
+    rotation: [[i32; 3]; 3],
+    // the scanner's position relative to scanner 0
+    offset: Pos,
+    // the scanner it was aligned against, and how many beacons they have in common
+    parent: Option<usize>,
+    overlap: usize,
+}
+
+#[derive(Debug, PartialEq)]
+struct BeaconMap {
+    beacons: HashSet<Pos>,
+    scanners: Vec<ScannerAlignment>,
+}
+
+impl BeaconMap {
+    fn scanner_positions(&self) -> Vec<Pos> {
+        self.scanners.iter().map(|s| s.offset).collect()
+    }
+
+    fn sorted_beacons(&self) -> Vec<Pos> {
+        let mut beacons: Vec<Pos> = self.beacons.iter().copied().collect();
+        beacons.sort_unstable_by_key(|p| (p.x, p.y, p.z));
+        beacons
+    }
+
+    fn to_json(&self) -> String {
+        let pos = |p: &Pos| format!("[{},{},{}]", p.x, p.y, p.z);
+        let beacons: Vec<String> = self.sorted_beacons().iter().map(pos).collect();
+        let scanners: Vec<String> = self
+            .scanners
+            .iter()
+            .map(|s| {
+                format!(
+                    "{{\"scanner\":{},\"position\":{},\"orientation\":{},\"rotation\":[{}],\"parent\":{},\"overlap\":{}}}",
+                    s.scan_number,
+                    pos(&s.offset),
+                    s.orientation,
+                    s.rotation
+                        .iter()
+                        .map(|row| format!("[{},{},{}]", row[0], row[1], row[2]))
+                        .collect::<Vec<_>>()
+                        .join(","),
+                    s.parent.map_or("null".to_string(), |p| p.to_string()),
+                    s.overlap
+                )
+            })
+            .collect();
+        format!(
+            "{{\"beacons\":[{}],\"scanners\":[\n{}\n]}}\n",
+            beacons.join(","),
+            scanners.join(",\n")
+        )
+    }
+
+    // beacons (white) and scanners (red) as coloured points
+    fn coloured_points(&self) -> Vec<(Pos, [u8; 3])> {
+        self.sorted_beacons()
+            .into_iter()
+            .map(|p| (p, [255, 255, 255]))
+            .chain(self.scanners.iter().map(|s| (s.offset, [255, 0, 0])))
+            .collect()
+    }
+
+    // ASCII PLY point cloud
+    fn to_ply(&self) -> String {
+        let points = self.coloured_points();
+        let mut s = format!(
+            "ply\nformat ascii 1.0\nelement vertex {}\n\
+             property int x\nproperty int y\nproperty int z\n\
+             property uchar red\nproperty uchar green\nproperty uchar blue\nend_header\n",
+            points.len()
+        );
+        for (p, c) in points {
+            s.push_str(&format!(
+                "{} {} {} {} {} {}\n",
+                p.x, p.y, p.z, c[0], c[1], c[2]
+            ));
         }
+        s
     }
 
-    (beacons, scanners)
+    // XYZ point cloud, one "x y z r g b" line per point
+    fn to_xyz(&self) -> String {
+        self.coloured_points()
+            .iter()
+            .map(|(p, c)| format!("{} {} {} {} {} {}\n", p.x, p.y, p.z, c[0], c[1], c[2]))
+            .collect()
+    }
+}
+
+fn search(scans: &[Scan], options: &AlignOptions) -> Result<BeaconMap, AlignError> {
+    if options.overlap == 0 {
+        return Err(AlignError::ZeroOverlap);
+    }
+
+    // Two scans sharing `overlap` beacons have at least overlap*(overlap-1)/2 pairwise distances
+    // in common, whatever their orientation.  Checking that first is far cheaper than trying
+    // every rotation and offset, and rules out almost every pair of scans that don't overlap.
+    let fingerprints: Vec<Vec<i64>> = if options.fingerprints {
+        scans.iter().map(fingerprint).collect()
+    } else {
+        vec![]
+    };
+    let min_common = options.overlap * options.overlap.saturating_sub(1) / 2;
+    let candidates = |i: usize, j: usize| {
+        !options.fingerprints || common_distances(&fingerprints[i], &fingerprints[j]) >= min_common
+    };
+
+    // everything will be relative to scan[0], so load its beacons into the
+    // map straight away
+    let mut beacons: HashSet<Pos> = HashSet::from_iter(scans[0].beacons.iter().copied());
+    let mut scanners: Vec<Option<ScannerAlignment>> = scans.iter().map(|_| None).collect();
+    scanners[0] = Some(ScannerAlignment {
+        scan_number: scans[0].number,
+        orientation: 0,
+        rotation: Pos::orientation_matrix(0),
+        offset: Pos::new(0, 0, 0),
+        parent: None,
+        overlap: scans[0].beacons.len(),
+    });
+
+    // known sets of points (re-orientated) for each scan that has been aligned
+    let mut known: Vec<Option<HashSet<Pos>>> = scans.iter().map(|_| None).collect();
+    known[0] = Some(beacons.clone());
+
+    // scans that have been aligned, but not yet compared against the remaining unaligned ones
+    let mut queue: VecDeque<usize> = VecDeque::from(vec![0]);
+
+    while let Some(i) = queue.pop_front() {
+        for (j, scan) in scans.iter().enumerate() {
+            if known[j].is_some() || !candidates(i, j) {
+                continue;
+            }
+            for rot in 0..options.orientations() {
+                let rotated_beacons = RotatedBeacons {
+                    scan_number: scan.number,
+                    rotation: rot,
+                    beacons: scan.beacons.iter().map(|p| p.orient(rot)).collect(),
+                };
+                let set = known[i].as_ref().unwrap();
+                if let Some(scanner_pos) = match_beacons(&rotated_beacons, set, options.overlap) {
+                    // println!("MATCHED!  {:?} is at {:?}", rotated_beacons, scanner_pos);
+                    // merge everything in this match into the set of known beacons
+                    let set: HashSet<Pos> = HashSet::from_iter(
+                        rotated_beacons
+                            .beacons
+                            .iter()
+                            .map(|p| p.offset(scanner_pos)),
+                    );
+                    beacons.extend(&set);
+                    // record how the scanner was aligned (its position is needed for part 2)
+                    scanners[j] = Some(ScannerAlignment {
+                        scan_number: scan.number,
+                        orientation: rot,
+                        rotation: Pos::orientation_matrix(rot),
+                        offset: scanner_pos,
+                        parent: Some(scans[i].number),
+                        overlap: set.intersection(known[i].as_ref().unwrap()).count(),
+                    });
+                    // store this set for later comparisons
+                    known[j] = Some(set);
+                    queue.push_back(j);
+                    break;
+                }
+            }
+        }
+    }
+
+    let unaligned: Vec<usize> = scans
+        .iter()
+        .zip(known.iter())
+        .filter(|(_, k)| k.is_none())
+        .map(|(scan, _)| scan.number)
+        .collect();
+    if !unaligned.is_empty() {
+        return Err(AlignError::Unaligned(unaligned));
+    }
+
+    Ok(BeaconMap {
+        beacons,
+        scanners: scanners.into_iter().map(Option::unwrap).collect(),
+    })
 }
 
 // assuming "other" is already correctly orientated list of beacons.
@@ -230,11 +482,11 @@ fn search(scans: &[Scan]) -> (HashSet<Pos>, Vec<Pos>) {
 // to be considered the correct rotation/offset.
 //
 // if found, returns the deduced scanner position.  otherwise returns None.
-fn match_beacons(rb: &RotatedBeacons, set: &HashSet<Pos>) -> Option<Pos> {
+fn match_beacons(rb: &RotatedBeacons, set: &HashSet<Pos>, overlap: usize) -> Option<Pos> {
     // now try to guess the offset.  any pos in 'beacons' might map to any pos in `set`
-    // but if it's not found by the time only 11 are left to check, this rotation will
+    // but if it's not found by the time only overlap-1 are left to check, this rotation will
     // not match.
-    for i in 0..rb.beacons.len() - 11 {
+    for i in 0..(rb.beacons.len() + 1).saturating_sub(overlap) {
         for known in set.iter() {
             let offset = Pos::new(
                 known.x - rb.beacons[i].x,
@@ -244,10 +496,10 @@ fn match_beacons(rb: &RotatedBeacons, set: &HashSet<Pos>) -> Option<Pos> {
             let mut count = 0;
             for b in rb.beacons.iter().map(|p| p.offset(offset)) {
                 if set.contains(&b) {
-                    if count == 11 {
+                    count += 1;
+                    if count == overlap {
                         return Some(offset);
                     }
-                    count += 1;
                 }
             }
         }
@@ -411,7 +663,300 @@ fn test() {
 30,-46,-14
 ";
     let scans = parse_input(test_input);
-    let (beacons, scanners) = search(&scans);
-    assert_eq!(79, beacons.len());
-    assert_eq!(3621, max_distance(&scanners));
+    let map = search(&scans, &AlignOptions::default()).unwrap();
+    assert_eq!(79, map.beacons.len());
+    assert_eq!(3621, max_distance(&map.scanner_positions()));
+    assert_eq!(
+        ScannerAlignment {
+            scan_number: 1,
+            orientation: 10,
+            rotation: [[-1, 0, 0], [0, 1, 0], [0, 0, -1]],
+            offset: Pos::new(68, -1246, -43),
+            parent: Some(0),
+            overlap: 12,
+        },
+        map.scanners[1]
+    );
+    assert_eq!(Some(1), map.scanners[4].parent);
+    assert_eq!(Pos::new(-20, -1133, 1061), map.scanners[4].offset);
+    for scanner in &map.scanners {
+        // every beacon the scanner saw, moved into scanner 0's frame, is on the map
+        let m = scanner.rotation;
+        for b in &scans[scanner.scan_number].beacons {
+            let p = Pos::new(
+                m[0][0] * b.x + m[0][1] * b.y + m[0][2] * b.z,
+                m[1][0] * b.x + m[1][1] * b.y + m[1][2] * b.z,
+                m[2][0] * b.x + m[2][1] * b.y + m[2][2] * b.z,
+            );
+            assert!(map.beacons.contains(&p.offset(scanner.offset)));
+        }
+    }
+
+    let json = map.to_json();
+    assert!(json.starts_with("{\"beacons\":[[-892,524,684],"));
+    assert!(json.contains(
+        "{\"scanner\":1,\"position\":[68,-1246,-43],\"orientation\":10,\"rotation\":[[-1,0,0],[0,1,0],[0,0,-1]],\"parent\":0,\"overlap\":12}"
+    ));
+    let ply = map.to_ply();
+    assert!(ply.starts_with("ply\nformat ascii 1.0\nelement vertex 84\n"));
+    assert!(ply.contains("end_header\n-892 524 684 255 255 255\n"));
+    assert!(ply.ends_with("\n-20 -1133 1061 255 0 0\n"));
+    let xyz = map.to_xyz();
+    assert_eq!(84, xyz.lines().count());
+    assert_eq!(Some("-892 524 684 255 255 255"), xyz.lines().next());
+    assert_eq!(
+        Err("unknown file type".to_string()),
+        export(test_input, "obj")
+    );
+
+    // the same scans, all mirrored, can only be aligned when reflections are allowed
+    let mirrored: Vec<Scan> = scans
+        .iter()
+        .map(|scan| Scan {
+            number: scan.number,
+            beacons: if scan.number == 0 {
+                scan.beacons.clone()
+            } else {
+                scan.beacons
+                    .iter()
+                    .map(|p| Pos::new(-p.x, p.y, p.z))
+                    .collect()
+            },
+        })
+        .collect();
+    assert_eq!(
+        Err(AlignError::Unaligned(vec![1, 2, 3, 4])),
+        search(&mirrored, &AlignOptions::default())
+    );
+    let options = AlignOptions {
+        reflections: true,
+        ..Default::default()
+    };
+    let map = search(&mirrored, &options).unwrap();
+    assert_eq!(79, map.beacons.len());
+    assert!(map.scanners[1].orientation >= 24);
+
+    // demanding more overlap than the example has makes alignment impossible
+    let options = AlignOptions {
+        overlap: 13,
+        ..Default::default()
+    };
+    assert_eq!(
+        Err(AlignError::Unaligned(vec![1, 2, 3, 4])),
+        search(&scans, &options)
+    );
+    let options = AlignOptions {
+        overlap: 0,
+        ..Default::default()
+    };
+    assert_eq!(Err(AlignError::ZeroOverlap), search(&scans, &options));
+}
+
+// Synthetic scanner data for testing.  Beacons are scattered uniformly through a cube, and each
+// scanner after the first is placed near an earlier one so that they see at least `overlap`
+// beacons in common; this guarantees the whole set can be aligned.
+#[cfg(test)]
+struct GeneratorOptions {
+    beacons: usize,
+    scanners: usize,
+    // scanners see beacons up to this far away on each axis
+    range: i32,
+    // beacons are placed in -world..=world on each axis
+    world: i32,
+    overlap: usize,
+    seed: u64,
+}
+
+#[cfg(test)]
+impl Default for GeneratorOptions {
+    fn default() -> Self {
+        GeneratorOptions {
+            beacons: 300,
+            scanners: 10,
+            range: 1000,
+            world: 2000,
+            overlap: 12,
+            seed: 2021,
+        }
+    }
+}
+
+// what the scanners were really looking at, relative to scanner 0 (which is never rotated)
+#[cfg(test)]
+struct GeneratedScans {
+    text: String,
+    // position and orientation applied to each scanner's readings
+    scanners: Vec<(Pos, i32)>,
+    // beacons seen by at least one scanner
+    beacons: HashSet<Pos>,
+}
+
+#[cfg(test)]
+fn generate_scans(options: &GeneratorOptions) -> GeneratedScans {
+    let mut rng = Rng::new(options.seed);
+    let world: Vec<Pos> = (0..options.beacons)
+        .map(|_| {
+            Pos::new(
+                rng.range(-options.world, options.world),
+                rng.range(-options.world, options.world),
+                rng.range(-options.world, options.world),
+            )
+        })
+        .collect();
+    let seen_by = |scanner: Pos| -> HashSet<Pos> {
+        world
+            .iter()
+            .filter(|b| {
+                (b.x - scanner.x).abs() <= options.range
+                    && (b.y - scanner.y).abs() <= options.range
+                    && (b.z - scanner.z).abs() <= options.range
+            })
+            .copied()
+            .collect()
+    };
+
+    let mut positions = vec![Pos::new(0, 0, 0)];
+    let mut seen = vec![seen_by(positions[0])];
+    let mut attempts = 0;
+    while positions.len() < options.scanners {
+        attempts += 1;
+        assert!(
+            attempts < options.scanners * 1000,
+            "Unable to place scanners with enough overlap, try more beacons"
+        );
+        let near = rng.range(0, positions.len() as i32 - 1) as usize;
+        let r = options.range;
+        let candidate = positions[near].offset(Pos::new(
+            rng.range(-r, r),
+            rng.range(-r, r),
+            rng.range(-r, r),
+        ));
+        let limit = options.world - r / 2;
+        if candidate.x.abs() > limit || candidate.y.abs() > limit || candidate.z.abs() > limit {
+            continue;
+        }
+        let beacons = seen_by(candidate);
+        if beacons.intersection(&seen[near]).count() >= options.overlap {
+            positions.push(candidate);
+            seen.push(beacons);
+        }
+    }
+
+    let mut text = String::new();
+    let mut scanners = vec![];
+    for (n, (scanner, beacons)) in positions.iter().zip(seen.iter()).enumerate() {
+        let rot = if n == 0 { 0 } else { rng.range(0, 23) };
+        let mut readings: Vec<Pos> = beacons
+            .iter()
+            .map(|b| Pos::new(b.x - scanner.x, b.y - scanner.y, b.z - scanner.z).rotate(rot))
+            .collect();
+        // shuffle, so the readings are in no particular order
+        readings.sort_unstable_by_key(|p| (p.x, p.y, p.z));
+        for i in (1..readings.len()).rev() {
+            readings.swap(i, rng.range(0, i as i32) as usize);
+        }
+        if n > 0 {
+            text.push('\n');
+        }
+        text.push_str(&format!("--- scanner {} ---\n", n));
+        for p in readings {
+            text.push_str(&format!("{:?}\n", p));
+        }
+        scanners.push((*scanner, rot));
+    }
+
+    GeneratedScans {
+        text,
+        scanners,
+        beacons: seen.into_iter().flatten().collect(),
+    }
+}
+
+#[test]
+fn test_generated() {
+    for seed in 1..=5 {
+        let generated = generate_scans(&GeneratorOptions {
+            seed,
+            ..Default::default()
+        });
+        let scans = parse_input(&generated.text);
+        assert_eq!(10, scans.len());
+
+        let map = search(&scans, &AlignOptions::default()).unwrap();
+        assert_eq!(generated.beacons, map.beacons);
+        for (alignment, &(pos, rot)) in map.scanners.iter().zip(generated.scanners.iter()) {
+            assert_eq!(pos, alignment.offset);
+            // the recovered orientation undoes the one that was applied
+            let p = Pos::new(1, 2, 3);
+            assert_eq!(p, p.rotate(rot).orient(alignment.orientation));
+        }
+    }
+}
+
+// cargo test --release bench_search -- --ignored --nocapture
+#[test]
+#[ignore]
+fn bench_search() {
+    use std::time::Instant;
+
+    // 100 scanners, ~35 beacons each
+    let generated = generate_scans(&GeneratorOptions {
+        beacons: 2400,
+        scanners: 100,
+        world: 4000,
+        ..Default::default()
+    });
+    let scans = parse_input(&generated.text);
+
+    let t0 = Instant::now();
+    let map = search(&scans, &AlignOptions::default()).unwrap();
+    let fingerprint_time = t0.elapsed().as_secs_f64();
+
+    let options = AlignOptions {
+        fingerprints: false,
+        ..Default::default()
+    };
+    let t0 = Instant::now();
+    let all = search(&scans, &options).unwrap();
+    let exhaustive_time = t0.elapsed().as_secs_f64();
+
+    println!(
+        "search of {} scanners: exhaustive {:.3}s, fingerprints {:.3}s ({:.1}x)",
+        scans.len(),
+        exhaustive_time,
+        fingerprint_time,
+        exhaustive_time / fingerprint_time
+    );
+    assert_eq!(all.beacons, map.beacons);
+    assert_eq!(all.scanner_positions(), map.scanner_positions());
+    assert_eq!(generated.beacons, map.beacons);
+}
+
+// cargo test --release bench_search_scaling -- --ignored --nocapture
+#[test]
+#[ignore]
+fn bench_search_scaling() {
+    use std::time::Instant;
+
+    // keep the beacon density the same as the world grows
+    for (scanners, world) in [(25, 2500), (50, 3200), (100, 4000), (200, 5000)] {
+        let beacons = (2400.0 * (world as f64 / 4000.0).powi(3)) as usize;
+        let generated = generate_scans(&GeneratorOptions {
+            beacons,
+            scanners,
+            world,
+            ..Default::default()
+        });
+        let scans = parse_input(&generated.text);
+
+        let t0 = Instant::now();
+        let map = search(&scans, &AlignOptions::default()).unwrap();
+        println!(
+            "{:4} scanners, {:5} beacons: {:.3}s",
+            scanners,
+            map.beacons.len(),
+            t0.elapsed().as_secs_f64()
+        );
+        assert_eq!(generated.beacons, map.beacons);
+    }
 }
@@ -1,32 +1,110 @@
 use crate::Part;
+use std::fmt;
 
 pub fn run(input: &str, part: Part) -> String {
-    let commands = parse_input(input);
+    let commands = match parse_input(input) {
+        Ok(commands) => commands,
+        Err(e) => return e.to_string(),
+    };
+    let semantics: &dyn Semantics = match part {
+        Part::One => &Simple,
+        Part::Two => &Aimed,
+    };
     let mut sub = Sub::new();
-    match part {
-        Part::One => sub.follow(&commands),
-        Part::Two => sub.follow2(&commands),
+    if crate::verbose() {
+        // the final position, and the first and last few steps that lead to it
+        const SHOWN: usize = 3;
+        let trace = sub.trace(&commands, semantics);
+        let mut lines = vec![sub.answer().to_string(), format!("final {}", sub)];
+        for (i, (command, step)) in commands.iter().zip(&trace).enumerate() {
+            if i == SHOWN && trace.len() > 2 * SHOWN {
+                lines.push(format!("... {} more commands", trace.len() - 2 * SHOWN));
+            }
+            if i < SHOWN || i + SHOWN >= trace.len() {
+                lines.push(format!("{:12} {}", command.to_string(), step));
+            }
+        }
+        lines.join("\n")
+    } else {
+        sub.follow(&commands, semantics);
+        format!("{}", sub.answer())
     }
-    format!("{}", sub.answer())
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 enum Direction {
     Forward,
     Down,
     Up,
 }
 
-#[derive(Debug)]
+#[derive(Debug, PartialEq)]
 struct Command {
     dir: Direction,
-    units: usize,
+    units: i64,
+}
+
+impl fmt::Display for Command {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let dir = match self.dir {
+            Direction::Forward => "forward",
+            Direction::Down => "down",
+            Direction::Up => "up",
+        };
+        write!(f, "{} {}", dir, self.units)
+    }
 }
 
+// depth is positive going down, so a sub above the surface has negative depth
+#[derive(Clone, Copy, Debug, PartialEq)]
 struct Sub {
-    hpos: usize,
-    depth: usize,
-    aim: usize,
+    hpos: i64,
+    depth: i64,
+    aim: i64,
+}
+
+impl fmt::Display for Sub {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "hpos {}, depth {}, aim {}",
+            self.hpos, self.depth, self.aim
+        )
+    }
+}
+
+// what a command does to the sub
+trait Semantics {
+    fn apply(&self, sub: &mut Sub, command: &Command);
+}
+
+// part 1: up and down change depth directly
+struct Simple;
+
+impl Semantics for Simple {
+    fn apply(&self, sub: &mut Sub, command: &Command) {
+        match command.dir {
+            Direction::Forward => sub.hpos += command.units,
+            Direction::Down => sub.depth += command.units,
+            Direction::Up => sub.depth -= command.units,
+        }
+    }
+}
+
+// part 2: up and down change aim, forward moves along it
+struct Aimed;
+
+impl Semantics for Aimed {
+    fn apply(&self, sub: &mut Sub, command: &Command) {
+        match command.dir {
+            Direction::Forward => {
+                sub.hpos += command.units;
+                sub.depth += sub.aim * command.units;
+            }
+            Direction::Down => sub.aim += command.units,
+            Direction::Up => sub.aim -= command.units,
+        }
+    }
 }
 
 impl Sub {
@@ -38,63 +116,86 @@ impl Sub {
         }
     }
 
-    fn follow(&mut self, commands: &[Command]) {
+    fn follow(&mut self, commands: &[Command], semantics: &dyn Semantics) {
         for command in commands {
-            match &command.dir {
-                Direction::Forward => self.hpos += command.units,
-                Direction::Down => self.depth += command.units,
-                Direction::Up => self.depth -= command.units,
-            }
+            semantics.apply(self, command);
         }
     }
 
-    fn follow2(&mut self, commands: &[Command]) {
-        for command in commands {
-            match &command.dir {
-                Direction::Forward => {
-                    self.hpos += command.units;
-                    self.depth += self.aim * command.units;
-                }
-                Direction::Down => self.aim += command.units,
-                Direction::Up => self.aim -= command.units,
-            }
-        }
+    // follow the commands, returning the position after each one
+    fn trace(&mut self, commands: &[Command], semantics: &dyn Semantics) -> Vec<Sub> {
+        commands
+            .iter()
+            .map(|command| {
+                semantics.apply(self, command);
+                *self
+            })
+            .collect()
     }
 
-    fn answer(&self) -> usize {
+    fn answer(&self) -> i64 {
         self.hpos * self.depth
     }
 }
 
-fn parse_input(input: &str) -> Vec<Command> {
+#[derive(Debug, PartialEq)]
+enum ParseError {
+    UnknownDirection { line: usize, word: String },
+    BadUnits { line: usize, word: String },
+    MissingUnits { line: usize },
+    ExtraInput { line: usize, word: String },
+}
+
+impl fmt::Display for ParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ParseError::UnknownDirection { line, word } => {
+                write!(f, "line {}: unknown direction {:?}", line, word)
+            }
+            ParseError::BadUnits { line, word } => {
+                write!(f, "line {}: invalid units {:?}", line, word)
+            }
+            ParseError::MissingUnits { line } => write!(f, "line {}: missing units", line),
+            ParseError::ExtraInput { line, word } => {
+                write!(f, "line {}: unexpected {:?} after the units", line, word)
+            }
+        }
+    }
+}
+
+fn parse_input(input: &str) -> Result<Vec<Command>, ParseError> {
     let mut commands = vec![];
-    for line in input.lines() {
-        let words: Vec<&str> = line.split(' ').collect();
-        match words[1].parse() {
-            Ok(units) => {
-                if line.starts_with("forward") {
-                    commands.push(Command {
-                        dir: Direction::Forward,
-                        units,
-                    })
-                } else if line.starts_with("down") {
-                    commands.push(Command {
-                        dir: Direction::Down,
-                        units,
-                    })
-                } else if line.starts_with("up") {
-                    commands.push(Command {
-                        dir: Direction::Up,
-                        units,
-                    })
-                } else {
-                    eprintln!("parse error: invalid direction: {}", line)
-                }
+    for (i, line) in input.lines().enumerate() {
+        let line_number = i + 1;
+        let mut words = line.split_whitespace();
+        let dir = match words.next() {
+            None => continue, // blank line
+            Some("forward") => Direction::Forward,
+            Some("down") => Direction::Down,
+            Some("up") => Direction::Up,
+            Some(word) => {
+                return Err(ParseError::UnknownDirection {
+                    line: line_number,
+                    word: word.to_string(),
+                })
             }
-            Err(e) => eprintln!("parse error: {}: {}", e, line),
+        };
+        let word = words
+            .next()
+            .ok_or(ParseError::MissingUnits { line: line_number })?;
+        let units = word.parse().map_err(|_| ParseError::BadUnits {
+            line: line_number,
+            word: word.to_string(),
+        })?;
+        if let Some(word) = words.next() {
+            return Err(ParseError::ExtraInput {
+                line: line_number,
+                word: word.to_string(),
+            });
         }
+        commands.push(Command { dir, units });
     }
-    commands
+    Ok(commands)
 }
 
 #[test]
@@ -108,15 +209,48 @@ up 3
 down 8
 forward 2
 ",
-    );
-    sub.follow(&commands);
+    )
+    .unwrap();
+    sub.follow(&commands, &Simple);
     assert_eq!(15, sub.hpos);
     assert_eq!(10, sub.depth);
     assert_eq!(150, sub.answer());
 
     sub = Sub::new();
-    sub.follow2(&commands);
+    sub.follow(&commands, &Aimed);
     assert_eq!(15, sub.hpos);
     assert_eq!(60, sub.depth);
     assert_eq!(900, sub.answer());
+
+    sub = Sub::new();
+    let trace = sub.trace(&commands, &Aimed);
+    assert_eq!(6, trace.len());
+    assert_eq!("hpos 13, depth 40, aim 5", trace[2].to_string());
+    assert_eq!(sub, trace[5]);
+
+    // going above the surface is allowed
+    let mut sub = Sub::new();
+    sub.follow(&parse_input("up 3\nforward 2\n").unwrap(), &Simple);
+    assert_eq!(-3, sub.depth);
+    assert_eq!(-6, sub.answer());
+
+    assert_eq!(
+        Err(ParseError::UnknownDirection {
+            line: 2,
+            word: "sideways".to_string()
+        }),
+        parse_input("up 1\nsideways 4\n")
+    );
+    assert_eq!(
+        "line 1: invalid units \"x\"",
+        parse_input("down x").unwrap_err().to_string()
+    );
+    assert_eq!(
+        Err(ParseError::MissingUnits { line: 3 }),
+        parse_input("up 1\n\nforward\n")
+    );
+    assert_eq!(
+        "line 1: unexpected \"7\" after the units",
+        parse_input("forward 5 7\n").unwrap_err().to_string()
+    );
 }
@@ -1,3 +1,5 @@
+use crate::animate::{paint, Simulation};
+use crate::raster::{Raster, Rasterize, BLACK, WHITE};
 use crate::Part;
 use std::collections::HashSet;
 use std::fmt;
@@ -30,6 +32,74 @@ fn enhance(image: &Image, alg: &[bool], count: usize) -> Image {
     img
 }
 
+pub fn simulation(input: &str) -> Box<dyn Simulation> {
+    let (alg, image) = parse_input(input);
+    Box::new(Enhancement { alg, image })
+}
+
+// the image before enhancing and after each of the 50 enhancements
+pub fn frames(input: &str) -> Vec<Raster> {
+    let (alg, mut image) = parse_input(input);
+    let mut frames = vec![image.rasterize()];
+    for _ in 0..50 {
+        image = image.enhance(&alg);
+        frames.push(image.rasterize());
+    }
+    frames
+}
+
+impl Rasterize for Image {
+    fn rasterize(&self) -> Raster {
+        if self.grid.is_empty() {
+            return Raster::new(0, 0, BLACK);
+        }
+        let rows = self.row_range();
+        let cols = self.col_range();
+        let mut raster = Raster::new(cols.len(), rows.len(), BLACK);
+        for &(row, col) in &self.grid {
+            raster.set(
+                (col - cols.start) as usize,
+                (row - rows.start) as usize,
+                WHITE,
+            );
+        }
+        raster
+    }
+}
+
+// image being enhanced, stops after the 50 enhancements of part 2
+struct Enhancement {
+    alg: Vec<bool>,
+    image: Image,
+}
+
+impl Simulation for Enhancement {
+    fn render(&self) -> String {
+        let mut s = String::new();
+        if !self.image.grid.is_empty() {
+            for row in self.image.row_range() {
+                for col in self.image.col_range() {
+                    s += &if self.image.grid.contains(&(row, col)) {
+                        paint("#", "93")
+                    } else {
+                        paint(".", "90")
+                    };
+                }
+                s += "\n";
+            }
+        }
+        s + &format!("{} lit pixels\n", self.image.grid.len())
+    }
+
+    fn advance(&mut self) -> bool {
+        if self.image.iterations == 50 {
+            return false;
+        }
+        self.image = self.image.enhance(&self.alg);
+        true
+    }
+}
+
 struct Image {
     grid: HashSet<(i32, i32)>,
     background: bool,
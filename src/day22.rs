@@ -1,3 +1,5 @@
+#[cfg(test)]
+use crate::rng::Rng;
 use crate::Part;
 use lazy_static::lazy_static;
 use regex::Regex;
@@ -6,47 +8,224 @@ use std::collections::HashSet;
 
 pub fn run(input: &str, part: Part) -> String {
     let steps = parse_input(input);
-    format!(
-        "{}",
-        match part {
-            Part::One => Core::new().reboot(&init_steps_only(&steps)),
-            Part::Two => Core::new().reboot(&steps),
+    match part {
+        // the init area is small enough that a dense grid is quickest
+        Part::One => format!(
+            "{}",
+            Core::with_strategy(Strategy::Grid).reboot_clipped(&steps, &[INIT_AREA])
+        ),
+        Part::Two if crate::verbose() => {
+            let reactor = Core::with_strategy(Strategy::Disjoint).reactor(&steps);
+            format!("{}\n{}", reactor.lit_count(), describe(&reactor))
         }
-    )
+        Part::Two => format!("{}", Core::new().reboot(&steps)),
+    }
 }
 
+// what is lit after the reboot, in more detail than the count
+fn describe(reactor: &Reactor) -> String {
+    let ranges = |c: &Cuboid| {
+        ["x", "y", "z"]
+            .iter()
+            .enumerate()
+            .map(|(i, axis)| format!("{}={}..{}", axis, c.min[i], c.max[i]))
+            .collect::<Vec<_>>()
+            .join(",")
+    };
+    let mut lines = vec![format!("{} disjoint lit cuboids", reactor.cuboids().len())];
+    if let Some(bounds) = reactor.bounding_box() {
+        lines.push(format!("bounding box {}", ranges(&bounds)));
+    }
+    lines.push(format!(
+        "{} lit in the init area, {}",
+        reactor.lit_volume_in(&INIT_AREA),
+        if reactor.is_on([0, 0, 0]) {
+            "including the centre"
+        } else {
+            "but not the centre"
+        }
+    ));
+    lines.join("\n")
+}
+
+// Steps may have any number of dimensions ("on x=1..2,y=3..4" is a 2D step), but the puzzle is
+// 3D so that is the default.
 #[derive(Clone, Debug)]
-struct Step {
+struct Step<const N: usize = 3> {
     on: bool,
-    x: (i32, i32),
-    y: (i32, i32),
-    z: (i32, i32),
+    cuboid: Cuboid<N>,
 }
 
-impl From<&str> for Step {
+impl<const N: usize> From<&str> for Step<N> {
     fn from(s: &str) -> Self {
         lazy_static! {
-            static ref RE: Regex =
-                Regex::new(r"^(on|off) x=(-?\d+)..(-?\d+),y=(-?\d+)..(-?\d+),z=(-?\d+)..(-?\d+)$")
-                    .unwrap();
+            static ref RE: Regex = Regex::new(r"^[a-z]=(-?\d+)\.\.(-?\d+)$").unwrap();
+        }
+        let (on, ranges) = s.split_once(' ').unwrap();
+        let ranges: Vec<&str> = ranges.split(',').collect();
+        assert_eq!(N, ranges.len(), "Expected {} dimensions in {:?}", N, s);
+
+        let mut cuboid = Cuboid {
+            min: [0; N],
+            max: [0; N],
+        };
+        for (i, range) in ranges.iter().enumerate() {
+            let m = RE.captures(range).unwrap();
+            cuboid.min[i] = m[1].parse().unwrap();
+            cuboid.max[i] = m[2].parse().unwrap();
         }
-        let m = RE.captures(s).unwrap();
         Self {
-            on: &m[1] == "on",
-            x: (m[2].parse().unwrap(), m[3].parse().unwrap()),
-            y: (m[4].parse().unwrap(), m[5].parse().unwrap()),
-            z: (m[6].parse().unwrap(), m[7].parse().unwrap()),
+            on: match on {
+                "on" => true,
+                "off" => false,
+                _ => panic!("Unexpected instruction: {:?}", on),
+            },
+            cuboid,
+        }
+    }
+}
+
+// inclusive range of cubes on each axis
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+struct Cuboid<const N: usize = 3> {
+    min: [i32; N],
+    max: [i32; N],
+}
+
+// part 1 only considers cubes in this region
+const INIT_AREA: Cuboid = Cuboid {
+    min: [-50; 3],
+    max: [50; 3],
+};
+
+impl<const N: usize> Cuboid<N> {
+    fn volume(&self) -> i64 {
+        (0..N)
+            .map(|i| (self.max[i] - self.min[i] + 1) as i64)
+            .product()
+    }
+
+    fn intersection(&self, other: &Cuboid<N>) -> Option<Cuboid<N>> {
+        let mut c = *self;
+        for i in 0..N {
+            c.min[i] = self.min[i].max(other.min[i]);
+            c.max[i] = self.max[i].min(other.max[i]);
+            if c.min[i] > c.max[i] {
+                return None;
+            }
+        }
+        Some(c)
+    }
+
+    fn contains(&self, p: [i32; N]) -> bool {
+        (0..N).all(|i| self.min[i] <= p[i] && p[i] <= self.max[i])
+    }
+
+    // the parts of this cuboid outside `other`, as up to 2N disjoint cuboids
+    fn subtract(&self, other: &Cuboid<N>) -> Vec<Cuboid<N>> {
+        let overlap = match self.intersection(other) {
+            Some(overlap) => overlap,
+            None => return vec![*self],
+        };
+        let mut pieces = vec![];
+        let mut rest = *self;
+        // slice off the parts below and above the overlap on each axis in turn
+        for i in 0..N {
+            if rest.min[i] < overlap.min[i] {
+                let mut piece = rest;
+                piece.max[i] = overlap.min[i] - 1;
+                pieces.push(piece);
+                rest.min[i] = overlap.min[i];
+            }
+            if rest.max[i] > overlap.max[i] {
+                let mut piece = rest;
+                piece.min[i] = overlap.max[i] + 1;
+                pieces.push(piece);
+                rest.max[i] = overlap.max[i];
+            }
         }
+        pieces
     }
 }
 
-struct Core {}
+// The state of the reactor core after a sequence of steps, kept as a list of disjoint cuboids
+// which are entirely lit.
+#[derive(Clone, Debug)]
+struct Reactor<const N: usize = 3> {
+    lit: Vec<Cuboid<N>>,
+}
+
+impl<const N: usize> Reactor<N> {
+    fn new() -> Self {
+        Self { lit: vec![] }
+    }
+
+    fn apply(&mut self, step: &Step<N>) {
+        let cuboid = step.cuboid;
+        self.lit = self.lit.iter().flat_map(|c| c.subtract(&cuboid)).collect();
+        if step.on {
+            self.lit.push(cuboid);
+        }
+    }
+
+    fn lit_count(&self) -> usize {
+        self.lit.iter().map(|c| c.volume()).sum::<i64>() as usize
+    }
+
+    fn is_on(&self, p: [i32; N]) -> bool {
+        self.lit.iter().any(|c| c.contains(p))
+    }
+
+    // number of lit cubes inside `region`
+    fn lit_volume_in(&self, region: &Cuboid<N>) -> usize {
+        self.lit
+            .iter()
+            .filter_map(|c| c.intersection(region))
+            .map(|c| c.volume())
+            .sum::<i64>() as usize
+    }
+
+    // smallest cuboid containing every lit cube
+    fn bounding_box(&self) -> Option<Cuboid<N>> {
+        let mut cuboids = self.lit.iter();
+        let mut bounds = *cuboids.next()?;
+        for c in cuboids {
+            for i in 0..N {
+                bounds.min[i] = bounds.min[i].min(c.min[i]);
+                bounds.max[i] = bounds.max[i].max(c.max[i]);
+            }
+        }
+        Some(bounds)
+    }
+
+    fn cuboids(&self) -> &[Cuboid<N>] {
+        &self.lit
+    }
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+enum Strategy {
+    // coordinate compression into a dense grid with a cell between each pair of distinct
+    // coordinates on every axis.  memory grows with the number of steps to the power N.
+    Grid,
+    // inclusion-exclusion: keep a list of cuboids with a count of +n or -n each, so that the sum
+    // of count * volume is the number of lit cubes.  memory grows with the number of overlaps.
+    #[default]
+    SignedCuboids,
+    // a Reactor: split existing lit cuboids around each step so that they never overlap.
+    // slower than SignedCuboids, but the result can be queried.
+    Disjoint,
+}
+
+#[derive(Default)]
+struct Core {
+    strategy: Strategy,
+}
 
 fn build_coords(index: Vec<(i32, i32)>) -> Vec<i32> {
     let mut numbers: Vec<i32> = index
         .iter()
-        .map(|p| vec![p.0, p.1 + 1])
-        .flatten()
+        .flat_map(|p| vec![p.0, p.1 + 1])
         .collect::<HashSet<i32>>()
         .into_iter()
         .collect();
@@ -54,56 +233,154 @@ fn build_coords(index: Vec<(i32, i32)>) -> Vec<i32> {
     numbers
 }
 
+// call f with every index in the box lo..hi (exclusive on each axis)
+fn for_each_index<const N: usize, F: FnMut([usize; N])>(lo: [usize; N], hi: [usize; N], mut f: F) {
+    if (0..N).any(|i| lo[i] >= hi[i]) {
+        return;
+    }
+    let mut index = lo;
+    loop {
+        f(index);
+        // advance like an odometer, last axis fastest
+        let mut axis = N;
+        loop {
+            if axis == 0 {
+                return;
+            }
+            axis -= 1;
+            index[axis] += 1;
+            if index[axis] < hi[axis] {
+                break;
+            }
+            index[axis] = lo[axis];
+        }
+    }
+}
+
 impl Core {
     fn new() -> Self {
-        Self {}
+        Self::default()
     }
 
-    #[allow(clippy::needless_range_loop)]
-    fn reboot(&self, steps: &[Step]) -> usize {
-        let x_coords = build_coords(steps.iter().map(|step| step.x).collect());
-        let y_coords = build_coords(steps.iter().map(|step| step.y).collect());
-        let z_coords = build_coords(steps.iter().map(|step| step.z).collect());
+    fn with_strategy(strategy: Strategy) -> Self {
+        Self { strategy }
+    }
 
-        let x_map: HashMap<i32, usize> =
-            HashMap::from_iter(x_coords.iter().enumerate().map(|(i, c)| (*c, i)));
-        let y_map: HashMap<i32, usize> =
-            HashMap::from_iter(y_coords.iter().enumerate().map(|(i, c)| (*c, i)));
-        let z_map: HashMap<i32, usize> =
-            HashMap::from_iter(z_coords.iter().enumerate().map(|(i, c)| (*c, i)));
+    fn reboot<const N: usize>(&self, steps: &[Step<N>]) -> usize {
+        match self.strategy {
+            Strategy::Grid => Self::reboot_grid(steps),
+            Strategy::SignedCuboids => Self::reboot_signed(steps),
+            Strategy::Disjoint => self.reactor(steps).lit_count(),
+        }
+    }
 
-        let mut cubes = vec![vec![vec![false; z_coords.len()]; y_coords.len()]; x_coords.len()];
+    // count lit cubes only within the given regions (cubes where regions overlap count once)
+    fn reboot_clipped<const N: usize>(&self, steps: &[Step<N>], regions: &[Cuboid<N>]) -> usize {
+        let mut pieces = Reactor::new();
+        for &cuboid in regions {
+            pieces.apply(&Step { on: true, cuboid });
+        }
+        pieces
+            .cuboids()
+            .iter()
+            .map(|piece| self.reboot(&clip_steps(steps, piece)))
+            .sum()
+    }
 
+    fn reactor<const N: usize>(&self, steps: &[Step<N>]) -> Reactor<N> {
+        let mut reactor = Reactor::new();
         for step in steps {
-            let x0 = x_map[&step.x.0];
-            let x1 = x_map[&(step.x.1 + 1)];
-            let y0 = y_map[&step.y.0];
-            let y1 = y_map[&(step.y.1 + 1)];
-            let z0 = z_map[&step.z.0];
-            let z1 = z_map[&(step.z.1 + 1)];
-
-            for x in x0..x1 {
-                for y in y0..y1 {
-                    for z in z0..z1 {
-                        cubes[x][y][z] = step.on;
-                    }
-                }
+            reactor.apply(step);
+        }
+        reactor
+    }
+
+    fn reboot_grid<const N: usize>(steps: &[Step<N>]) -> usize {
+        if steps.is_empty() {
+            return 0;
+        }
+        let coords: Vec<Vec<i32>> = (0..N)
+            .map(|i| {
+                build_coords(
+                    steps
+                        .iter()
+                        .map(|step| (step.cuboid.min[i], step.cuboid.max[i]))
+                        .collect(),
+                )
+            })
+            .collect();
+        let maps: Vec<HashMap<i32, usize>> = coords
+            .iter()
+            .map(|c| HashMap::from_iter(c.iter().enumerate().map(|(i, c)| (*c, i))))
+            .collect();
+
+        // flattened grid, with one cell fewer than the number of coords on each axis
+        let mut size = [0; N];
+        let mut strides = [0; N];
+        let mut cells = 1;
+        for i in (0..N).rev() {
+            size[i] = coords[i].len() - 1;
+            strides[i] = cells;
+            cells *= size[i];
+        }
+        let flat = |index: [usize; N]| (0..N).map(|i| index[i] * strides[i]).sum::<usize>();
+        let mut cubes = vec![false; cells];
+
+        for step in steps {
+            let mut lo = [0; N];
+            let mut hi = [0; N];
+            for i in 0..N {
+                lo[i] = maps[i][&step.cuboid.min[i]];
+                hi[i] = maps[i][&(step.cuboid.max[i] + 1)];
             }
+            for_each_index(lo, hi, |index| cubes[flat(index)] = step.on);
         }
 
         let mut count: usize = 0;
-        for x in 0..(x_coords.len() - 1) {
-            for y in 0..(y_coords.len() - 1) {
-                for z in 0..(z_coords.len() - 1) {
-                    if cubes[x][y][z] {
-                        count += (x_coords[x + 1] - x_coords[x]) as usize
-                            * (y_coords[y + 1] - y_coords[y]) as usize
-                            * (z_coords[z + 1] - z_coords[z]) as usize
-                    }
+        for_each_index([0; N], size, |index| {
+            if cubes[flat(index)] {
+                count += (0..N)
+                    .map(|i| (coords[i][index[i] + 1] - coords[i][index[i]]) as usize)
+                    .product::<usize>();
+            }
+        });
+        count
+    }
+
+    fn reboot_signed<const N: usize>(steps: &[Step<N>]) -> usize {
+        Self::signed_cuboids(steps)
+            .iter()
+            .map(|(c, &n)| c.volume() * n)
+            .sum::<i64>() as usize
+    }
+
+    fn signed_cuboids<const N: usize>(steps: &[Step<N>]) -> HashMap<Cuboid<N>, i64> {
+        let mut cuboids: HashMap<Cuboid<N>, i64> = HashMap::new();
+
+        for step in steps {
+            let cuboid = step.cuboid;
+            // cancel out whatever is already counted inside this step's cuboid, so that it
+            // ends up counted exactly 0 times ...
+            let mut update: HashMap<Cuboid<N>, i64> = HashMap::new();
+            for (c, &n) in cuboids.iter() {
+                if let Some(overlap) = c.intersection(&cuboid) {
+                    *update.entry(overlap).or_default() -= n;
+                }
+            }
+            // ... or once, for "on"
+            if step.on {
+                *update.entry(cuboid).or_default() += 1;
+            }
+            for (c, n) in update {
+                let count = cuboids.entry(c).or_default();
+                *count += n;
+                if *count == 0 {
+                    cuboids.remove(&c);
                 }
             }
         }
-        count
+
+        cuboids
     }
 }
 
@@ -111,30 +388,17 @@ fn parse_input(input: &str) -> Vec<Step> {
     input.lines().map(Step::from).collect()
 }
 
-fn init_steps_only(steps: &[Step]) -> Vec<Step> {
-    // for part 1, munge steps to not modify anything outside the init area
-    // maybe simpler than modifying the count/processing functions
-    let mut new = vec![];
-
-    for step in steps {
-        if !(step.x.1 < -50
-            || step.x.0 > 50
-            || step.y.1 < -50
-            || step.y.0 > 50
-            || step.z.1 < -50
-            || step.z.0 > 50)
-        {
-            // inside or partially inside the init procedure cube
-            new.push(Step {
+// munge steps to not modify anything outside `region`
+fn clip_steps<const N: usize>(steps: &[Step<N>], region: &Cuboid<N>) -> Vec<Step<N>> {
+    steps
+        .iter()
+        .filter_map(|step| {
+            step.cuboid.intersection(region).map(|cuboid| Step {
                 on: step.on,
-                x: (step.x.0.max(-50), step.x.1.min(50)),
-                y: (step.y.0.max(-50), step.y.1.min(50)),
-                z: (step.z.0.max(-50), step.z.1.min(50)),
+                cuboid,
             })
-        }
-    }
-
-    new
+        })
+        .collect()
 }
 
 #[test]
@@ -151,6 +415,35 @@ on x=10..10,y=10..10,z=10..10
     assert_eq!(27 + 19, core.reboot(&steps[0..=1]));
     assert_eq!(27 + 19 - 8, core.reboot(&steps[0..=2]));
     assert_eq!(39, core.reboot(&steps));
+    let grid = Core::with_strategy(Strategy::Grid);
+    assert_eq!(27 + 19 - 8, grid.reboot(&steps[0..=2]));
+    assert_eq!(39, grid.reboot(&steps));
+    let disjoint = Core::with_strategy(Strategy::Disjoint);
+    assert_eq!(39, disjoint.reboot(&steps));
+
+    let reactor = core.reactor(&steps);
+    assert!(reactor.is_on([10, 10, 10]));
+    assert!(!reactor.is_on([11, 11, 11]));
+    assert!(reactor.is_on([13, 13, 13]));
+    assert!(!reactor.is_on([14, 14, 14]));
+    assert_eq!(
+        Some(Cuboid {
+            min: [10, 10, 10],
+            max: [13, 13, 13]
+        }),
+        reactor.bounding_box()
+    );
+    let corner = Cuboid {
+        min: [0, 0, 0],
+        max: [10, 10, 10],
+    };
+    assert_eq!(1, reactor.lit_volume_in(&corner));
+    for (i, a) in reactor.cuboids().iter().enumerate() {
+        for b in reactor.cuboids().iter().skip(i + 1) {
+            assert_eq!(None, a.intersection(b));
+        }
+    }
+    assert_eq!(None, Reactor::<3>::new().bounding_box());
 
     let test_input = "\
 on x=-20..26,y=-36..17,z=-47..7
@@ -176,8 +469,10 @@ on x=-41..9,y=-7..43,z=-33..15
 on x=-54112..-39298,y=-85059..-49293,z=-27449..7877
 on x=967..23432,y=45373..81175,z=27513..53682
 ";
-    let steps = init_steps_only(&parse_input(test_input));
-    assert_eq!(590784, core.reboot(&steps));
+    let steps = parse_input(test_input);
+    assert_eq!(590784, core.reboot_clipped(&steps, &[INIT_AREA]));
+    assert_eq!(590784, grid.reboot_clipped(&steps, &[INIT_AREA]));
+    assert_eq!(590784, disjoint.reboot_clipped(&steps, &[INIT_AREA]));
 
     let test_input = "\
 on x=-5..47,y=-31..22,z=-19..33
@@ -243,5 +538,113 @@ off x=-93533..-4276,y=-16170..68771,z=-104985..-24507
 ";
     let steps = parse_input(test_input);
     assert_eq!(2758514936282235, core.reboot(&steps));
-    assert_eq!(474140, core.reboot(&init_steps_only(&steps)));
+    assert_eq!(474140, core.reboot_clipped(&steps, &[INIT_AREA]));
+    assert_eq!(2758514936282235, grid.reboot(&steps));
+    let reactor = disjoint.reactor(&steps);
+    assert_eq!(2758514936282235, reactor.lit_count());
+    assert_eq!(474140, reactor.lit_volume_in(&INIT_AREA));
+    assert!(describe(&reactor).ends_with("\n474140 lit in the init area, including the centre"));
+
+    // 2D: a 10x10 square with a corner switched off, clipped to one or two overlapping regions
+    let steps: Vec<Step<2>> = "on x=0..9,y=0..9\noff x=5..14,y=5..14"
+        .lines()
+        .map(Step::from)
+        .collect();
+    let regions = [
+        Cuboid {
+            min: [0, 0],
+            max: [4, 4],
+        },
+        Cuboid {
+            min: [3, 3],
+            max: [6, 6],
+        },
+    ];
+    for core in [&core, &grid, &disjoint] {
+        assert_eq!(75, core.reboot(&steps));
+        assert_eq!(25, core.reboot_clipped(&steps, &regions[0..1]));
+        assert_eq!(25 + 8, core.reboot_clipped(&steps, &regions));
+    }
+
+    // 4D: two overlapping 2x2x2x2 hypercubes, with one corner switched off
+    let steps: Vec<Step<4>> = "\
+on x=0..1,y=0..1,z=0..1,w=0..1
+on x=1..2,y=1..2,z=1..2,w=1..2
+off x=0..0,y=0..0,z=0..0,w=0..0"
+        .lines()
+        .map(Step::from)
+        .collect();
+    for core in [&core, &grid, &disjoint] {
+        assert_eq!(16 + 16 - 1 - 1, core.reboot(&steps));
+    }
+    assert!(!core.reactor(&steps).is_on([0, 0, 0, 0]));
+    assert!(core.reactor(&steps).is_on([2, 2, 2, 2]));
+}
+
+// Random steps in the style of the puzzle input: large, overlapping cuboids
+#[cfg(test)]
+fn random_steps(count: usize, seed: u64) -> Vec<Step> {
+    let mut rng = Rng::new(seed);
+    (0..count)
+        .map(|_| {
+            let mut range = || {
+                let start = rng.range(-50000, 30000);
+                (start, start + rng.range(5000, 30000))
+            };
+            let (x, y, z) = (range(), range(), range());
+            Step {
+                cuboid: Cuboid {
+                    min: [x.0, y.0, z.0],
+                    max: [x.1, y.1, z.1],
+                },
+                on: rng.range(0, 3) > 0,
+            }
+        })
+        .collect()
+}
+
+// cargo test --release bench_reboot -- --ignored --nocapture
+#[test]
+#[ignore]
+fn bench_reboot() {
+    use std::mem::size_of;
+    use std::time::Instant;
+
+    for count in [250, 500, 1000, 2000] {
+        let steps = random_steps(count, 0x2021_1222);
+
+        let t0 = Instant::now();
+        let signed = Core::with_strategy(Strategy::SignedCuboids).reboot(&steps);
+        let signed_time = t0.elapsed().as_secs_f64();
+        let signed_bytes = Core::signed_cuboids(&steps).len() * size_of::<(Cuboid, i64)>();
+
+        let cells: usize = (0..3)
+            .map(|i| {
+                build_coords(
+                    steps
+                        .iter()
+                        .map(|step| (step.cuboid.min[i], step.cuboid.max[i]))
+                        .collect(),
+                )
+                .len()
+            })
+            .product();
+        let grid = if cells <= 1 << 30 {
+            let t0 = Instant::now();
+            let grid = Core::with_strategy(Strategy::Grid).reboot(&steps);
+            assert_eq!(signed, grid);
+            format!("{:.3}s", t0.elapsed().as_secs_f64())
+        } else {
+            "skipped".to_string()
+        };
+
+        println!(
+            "{:5} steps: grid {:>8} ({:6} MB), signed cuboids {:.3}s ({:4} MB)",
+            count,
+            grid,
+            cells / 1_000_000,
+            signed_time,
+            signed_bytes / 1_000_000,
+        );
+    }
 }
@@ -1,3 +1,5 @@
+use crate::animate::{paint, Simulation};
+use crate::raster::{Raster, Rasterize};
 use crate::Part;
 use std::fmt;
 
@@ -20,6 +22,20 @@ fn part1(mut map: Map) -> usize {
     step
 }
 
+pub fn simulation(input: &str) -> Box<dyn Simulation> {
+    Box::new(parse_input(input))
+}
+
+// every state until the cucumbers stop moving
+pub fn frames(input: &str) -> Vec<Raster> {
+    let mut map = parse_input(input);
+    let mut frames = vec![map.rasterize()];
+    while map.step() {
+        frames.push(map.rasterize());
+    }
+    frames
+}
+
 #[derive(Clone)]
 enum Cucumber {
     EastFacing,
@@ -90,6 +106,44 @@ impl Map {
     }
 }
 
+impl Simulation for Map {
+    fn render(&self) -> String {
+        let mut s = String::new();
+        for row in &self.cucumbers {
+            for c in row {
+                s += &match c {
+                    Cucumber::EastFacing => paint(">", "32"),
+                    Cucumber::SouthFacing => paint("v", "36"),
+                    Cucumber::Empty => paint(".", "90"),
+                };
+            }
+            s += "\n";
+        }
+        s
+    }
+
+    // stops once no cucumber can move
+    fn advance(&mut self) -> bool {
+        self.step()
+    }
+}
+
+impl Rasterize for Map {
+    fn rasterize(&self) -> Raster {
+        let mut raster = Raster::new(self.cucumbers[0].len(), self.cucumbers.len(), [10, 20, 40]);
+        for (y, row) in self.cucumbers.iter().enumerate() {
+            for (x, c) in row.iter().enumerate() {
+                match c {
+                    Cucumber::EastFacing => raster.set(x, y, [40, 200, 60]),
+                    Cucumber::SouthFacing => raster.set(x, y, [60, 160, 230]),
+                    Cucumber::Empty => (),
+                }
+            }
+        }
+        raster
+    }
+}
+
 impl fmt::Display for Map {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut ef = 0;
@@ -1,92 +1,163 @@
 use crate::Part;
+use num_bigint::BigUint;
+use std::fmt;
 
 pub fn run(input: &str, part: Part) -> String {
-    let input = parse_input(input);
-    format!(
-        "{}",
-        match part {
-            Part::One => {
-                let (gamma, epsilon) = calc_power_consumption(&input);
-                gamma * epsilon
+    let report = parse_input(input);
+    match part {
+        Part::One => {
+            let (gamma, epsilon) = calc_power_consumption(&report);
+            if crate::verbose() {
+                format!("{}\n{}", product(gamma, epsilon), bit_report(&report))
+            } else {
+                format!("{}", product(gamma, epsilon))
             }
-            Part::Two => calc_oxygen_generator_rating(&input) * calc_co2_scrubber_rating(&input),
         }
-    )
+        Part::Two => match (
+            calc_oxygen_generator_rating(&report),
+            calc_co2_scrubber_rating(&report),
+        ) {
+            (Ok(oxygen), Ok(co2)) => format!("{}", product(oxygen, co2)),
+            (Err(e), _) => format!("oxygen generator rating: {}", e),
+            (_, Err(e)) => format!("CO2 scrubber rating: {}", e),
+        },
+    }
 }
 
-fn calc_power_consumption(input: &[usize]) -> (usize, usize) {
-    let mut gamma = 0;
-    let mut epsilon = 0;
-    let half = input.len() / 2;
-    for n in 0..=15 {
-        let count = input
-            .iter()
-            .filter(|&row| row & (1 << (15 - n)) > 0)
-            .count();
-        if count > 0 {
-            if count > half {
-                gamma += 1 << (15 - n);
-            } else {
-                epsilon += 1 << (15 - n);
+// values can be up to 128 bits, so their product needs up to 256
+fn product(a: u128, b: u128) -> BigUint {
+    BigUint::from(a) * b
+}
+
+// diagnostic report, all values have the same number of bits
+struct Report {
+    values: Vec<u128>,
+    width: usize,
+}
+
+impl Report {
+    // mask for bit n counting from the left, most significant first
+    fn mask(&self, n: usize) -> u128 {
+        1 << (self.width - 1 - n)
+    }
+}
+
+#[derive(Debug, PartialEq)]
+struct BitCount {
+    ones: usize,
+    zeros: usize,
+}
+
+#[derive(Debug, PartialEq)]
+enum RatingError {
+    NoValues,
+    NotUnique(Vec<u128>), // values left after every bit was considered
+}
+
+impl fmt::Display for RatingError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RatingError::NoValues => write!(f, "no values to rate"),
+            RatingError::NotUnique(values) => {
+                write!(f, "{} values left after filtering", values.len())
             }
         }
     }
-    (gamma, epsilon)
 }
 
-fn calc_oxygen_generator_rating(input: &[usize]) -> usize {
-    let mut list = input.to_owned();
-    for n in 0..=15 {
-        let count = list.iter().filter(|&row| row & (1 << (15 - n)) > 0).count();
-        if count > 0 {
-            if count * 2 >= list.len() {
-                list = list
-                    .into_iter()
-                    .filter(|&row| row & (1 << (15 - n)) > 0)
-                    .collect::<Vec<usize>>();
-            } else {
-                list = list
-                    .into_iter()
-                    .filter(|&row| row & (1 << (15 - n)) == 0)
-                    .collect::<Vec<usize>>();
-            }
-            if list.len() == 1 {
-                return list[0];
+// how many ones and zeros there are at each bit, most significant first
+fn bit_counts(report: &Report) -> Vec<BitCount> {
+    (0..report.width)
+        .map(|n| {
+            let ones = report
+                .values
+                .iter()
+                .filter(|&&v| v & report.mask(n) > 0)
+                .count();
+            BitCount {
+                ones,
+                zeros: report.values.len() - ones,
             }
+        })
+        .collect()
+}
+
+// the bit counts with the gamma and epsilon bit they give
+fn bit_report(report: &Report) -> String {
+    bit_counts(report)
+        .iter()
+        .enumerate()
+        .map(|(n, count)| {
+            let gamma = (count.ones > count.zeros) as u8;
+            format!(
+                "bit {:3}: {:5} ones {:5} zeros, gamma {} epsilon {}",
+                n,
+                count.ones,
+                count.zeros,
+                gamma,
+                1 - gamma
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+fn calc_power_consumption(report: &Report) -> (u128, u128) {
+    let mut gamma = 0;
+    let mut epsilon = 0;
+    for (n, count) in bit_counts(report).iter().enumerate() {
+        if count.ones > count.zeros {
+            gamma += report.mask(n);
+        } else {
+            epsilon += report.mask(n);
         }
     }
-    0
+    (gamma, epsilon)
 }
 
-fn calc_co2_scrubber_rating(input: &[usize]) -> usize {
-    let mut list = input.to_owned();
-    for n in 0..=15 {
-        let count = list.iter().filter(|&row| row & (1 << (15 - n)) > 0).count();
-        if count > 0 {
-            if count * 2 >= list.len() {
-                list = list
-                    .into_iter()
-                    .filter(|&row| row & (1 << (15 - n)) == 0)
-                    .collect::<Vec<usize>>();
-            } else {
-                list = list
-                    .into_iter()
-                    .filter(|&row| row & (1 << (15 - n)) > 0)
-                    .collect::<Vec<usize>>();
-            }
-            if list.len() == 1 {
-                return list[0];
-            }
+// filter values one bit at a time, keeping those with the most common bit (or
+// least common) until only one is left.  a bit where every value agrees
+// doesn't filter anything out.
+fn filter_rating(report: &Report, most_common: bool) -> Result<u128, RatingError> {
+    let mut list = report.values.clone();
+    for n in 0..report.width {
+        if list.len() <= 1 {
+            break;
         }
+        let mask = report.mask(n);
+        let count = list.iter().filter(|&&v| v & mask > 0).count();
+        if count == 0 || count == list.len() {
+            continue;
+        }
+        let keep_ones = (count * 2 >= list.len()) == most_common;
+        list.retain(|&v| (v & mask > 0) == keep_ones);
+    }
+    match list[..] {
+        [] => Err(RatingError::NoValues),
+        [value] => Ok(value),
+        _ => Err(RatingError::NotUnique(list)),
     }
-    0
 }
 
-fn parse_input(input: &str) -> Vec<usize> {
-    input
+fn calc_oxygen_generator_rating(report: &Report) -> Result<u128, RatingError> {
+    filter_rating(report, true)
+}
+
+fn calc_co2_scrubber_rating(report: &Report) -> Result<u128, RatingError> {
+    filter_rating(report, false)
+}
+
+fn parse_input(input: &str) -> Report {
+    let width = input.lines().map(|line| line.len()).max().unwrap_or(0);
+    assert!(width <= 128, "report lines are limited to 128 bits");
+    let values = input
         .lines()
-        .map(|line| usize::from_str_radix(line, 2).unwrap())
-        .collect()
+        .map(|line| {
+            assert_eq!(width, line.len(), "report lines differ in length: {}", line);
+            u128::from_str_radix(line, 2).unwrap()
+        })
+        .collect();
+    Report { values, width }
 }
 
 #[test]
@@ -104,8 +175,40 @@ fn test_calc() {
 00010
 01010
 ";
-    let input = parse_input(&test_input);
+    let input = parse_input(test_input);
     assert_eq!((22, 9), calc_power_consumption(&input));
-    assert_eq!(23, calc_oxygen_generator_rating(&input));
-    assert_eq!(10, calc_co2_scrubber_rating(&input));
+    assert_eq!(Ok(23), calc_oxygen_generator_rating(&input));
+    assert_eq!(Ok(10), calc_co2_scrubber_rating(&input));
+    assert_eq!(BitCount { ones: 7, zeros: 5 }, bit_counts(&input)[0]);
+    assert_eq!(
+        "bit   1:     5 ones     7 zeros, gamma 0 epsilon 1",
+        bit_report(&input).lines().nth(1).unwrap()
+    );
+
+    // leading zeros still count towards the width
+    let input = parse_input("0001\n0011\n0010\n");
+    assert_eq!(4, input.width);
+    assert_eq!((0b0011, 0b1100), calc_power_consumption(&input));
+
+    let wide = format!("1{}\n0{}\n", "0".repeat(127), "1".repeat(127));
+    let input = parse_input(&wide);
+    assert_eq!(Ok(1 << 127), calc_oxygen_generator_rating(&input));
+    assert_eq!(Ok(u128::MAX >> 1), calc_co2_scrubber_rating(&input));
+    // 2^99 * (2^99 - 1), far past u128
+    let wide = format!("1{}\n0{}\n", "0".repeat(99), "1".repeat(99));
+    assert_eq!(
+        "401734511064747568885490523084656825330436633744949857222656",
+        run(&wide, Part::Two)
+    );
+
+    let input = parse_input("101\n101\n010\n");
+    assert_eq!(
+        Err(RatingError::NotUnique(vec![5, 5])),
+        calc_oxygen_generator_rating(&input)
+    );
+    assert_eq!(Ok(2), calc_co2_scrubber_rating(&input));
+    assert_eq!(
+        Err(RatingError::NoValues),
+        calc_co2_scrubber_rating(&parse_input(""))
+    );
 }
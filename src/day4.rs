@@ -2,30 +2,114 @@ use crate::Part;
 use std::fmt;
 
 pub fn run(input: &str, part: Part) -> String {
-    let (numbers, boards) = parse_input(input);
-    format!(
-        "{}",
-        match part {
-            Part::One => play_to_win(numbers, boards),
-            Part::Two => play_to_lose(numbers, boards),
+    let (numbers, boards) = match parse_input(input) {
+        Ok(game) => game,
+        Err(e) => return e.to_string(),
+    };
+    let result = if crate::verbose() {
+        // also show what difference diagonal wins would make
+        let diagonal = play_for(&numbers, boards.clone(), Rules { diagonals: true }, part);
+        describe(&numbers, boards, part).and_then(|plain| {
+            diagonal.map(|d| format!("{}\nwith diagonals the score is {}", plain, d.score()))
+        })
+    } else {
+        play_for(&numbers, boards, Rules::default(), part).map(|result| result.score().to_string())
+    };
+    result.unwrap_or_else(|e| e.to_string())
+}
+
+fn play_for(
+    numbers: &[usize],
+    boards: Vec<Board>,
+    rules: Rules,
+    part: Part,
+) -> Result<GameResult, BingoError> {
+    match part {
+        Part::One => play_to_win(numbers, boards, rules),
+        Part::Two => play_to_lose(numbers, boards, rules),
+    }
+}
+
+// score for the part, followed by the finishing order or the full game log
+fn describe(numbers: &[usize], boards: Vec<Board>, part: Part) -> Result<String, BingoError> {
+    let log = play(numbers, boards, Rules::default());
+    Ok(match part {
+        Part::One => {
+            let order: Vec<String> = log.ranking().iter().map(|b| (b + 1).to_string()).collect();
+            format!(
+                "{}\nfinishing order: {}",
+                log.first_win()?.score(),
+                order.join(", ")
+            )
         }
-        .score()
-    )
+        Part::Two => format!(
+            "{}\n{}",
+            log.last_win()?.score(),
+            log.to_string().trim_end()
+        ),
+    })
 }
 
+// boards can be any size, rows and columns always win.  diagonals only exist
+// on square boards.
+#[derive(Clone, Copy, Debug, Default)]
+struct Rules {
+    diagonals: bool,
+}
+
+#[derive(Debug, PartialEq)]
+enum BingoError {
+    BadNumber(String),
+    EmptyBoard { board: usize },
+    RaggedBoard { board: usize, row: usize },
+    NoWinner,
+    NeverWins { board: usize },
+}
+
+impl fmt::Display for BingoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            BingoError::BadNumber(s) => write!(f, "{:?} is not a number", s),
+            BingoError::EmptyBoard { board } => write!(f, "board {} has no numbers", board + 1),
+            BingoError::RaggedBoard { board, row } => write!(
+                f,
+                "row {} of board {} is not the same length as the first row",
+                row + 1,
+                board + 1
+            ),
+            BingoError::NoWinner => write!(f, "no board wins"),
+            BingoError::NeverWins { board } => {
+                write!(
+                    f,
+                    "board {} never wins, so there is no last winner",
+                    board + 1
+                )
+            }
+        }
+    }
+}
+
+#[derive(Clone)]
 struct Board {
-    grid: [[usize; 5]; 5],
-    mark: [[bool; 5]; 5],
+    grid: Vec<Vec<usize>>,
+    mark: Vec<Vec<bool>>,
     done: bool,
 }
 
 impl fmt::Debug for Board {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let mut s = String::new();
-        for y in 0..5 {
-            s += &self.grid[y].map(|v| format!("{:2}", v)).join(" ");
+        for (row, mark) in self.grid.iter().zip(&self.mark) {
+            s += &row
+                .iter()
+                .map(|v| format!("{:2}", v))
+                .collect::<Vec<_>>()
+                .join(" ");
             s += "  ";
-            s += &self.mark[y].map(|v| if v { "#" } else { "." }).join("");
+            s += &mark
+                .iter()
+                .map(|&v| if v { "#" } else { "." })
+                .collect::<String>();
             s += "\n";
         }
         write!(f, "{}", s)
@@ -33,27 +117,39 @@ impl fmt::Debug for Board {
 }
 
 impl Board {
-    fn new(input: &[Vec<usize>]) -> Board {
-        let mut grid = [[0; 5]; 5];
-        for y in 0..5 {
-            for x in 0..5 {
-                grid[y][x] = input[y][x];
-            }
+    // board is only used for error messages
+    fn new(input: &[Vec<usize>], board: usize) -> Result<Board, BingoError> {
+        if input.is_empty() || input[0].is_empty() {
+            return Err(BingoError::EmptyBoard { board });
         }
-        Board {
-            grid,
-            mark: [[false; 5]; 5],
-            done: false,
+        if let Some(row) = input.iter().position(|row| row.len() != input[0].len()) {
+            return Err(BingoError::RaggedBoard { board, row });
         }
+        Ok(Board {
+            grid: input.to_vec(),
+            mark: input.iter().map(|row| vec![false; row.len()]).collect(),
+            done: false,
+        })
+    }
+
+    fn rows(&self) -> usize {
+        self.grid.len()
+    }
+
+    fn cols(&self) -> usize {
+        self.grid[0].len()
     }
 
     // returns true if this caused bingo
-    fn mark(&mut self, number: usize) -> bool {
-        for y in 0..5 {
-            for x in 0..5 {
+    fn mark(&mut self, number: usize, rules: Rules) -> bool {
+        for y in 0..self.rows() {
+            for x in 0..self.cols() {
                 if self.grid[y][x] == number {
                     self.mark[y][x] = true;
-                    if self.mark[y].iter().all(|v| *v) || self.mark.iter().all(|row| row[x]) {
+                    if self.mark[y].iter().all(|v| *v)
+                        || self.mark.iter().all(|row| row[x])
+                        || (rules.diagonals && self.diagonal_through(x, y))
+                    {
                         self.done = true;
                         return true;
                     } else {
@@ -65,12 +161,22 @@ impl Board {
         false
     }
 
+    // true if (x, y) is on a fully marked diagonal
+    fn diagonal_through(&self, x: usize, y: usize) -> bool {
+        let n = self.rows();
+        if n != self.cols() {
+            return false;
+        }
+        (x == y && (0..n).all(|i| self.mark[i][i]))
+            || (x + y == n - 1 && (0..n).all(|i| self.mark[i][n - 1 - i]))
+    }
+
     fn sum_unmarked_numbers(&self) -> usize {
         let mut sum = 0;
-        for y in 0..5 {
-            for x in 0..5 {
-                if !self.mark[y][x] {
-                    sum += self.grid[y][x]
+        for (row, mark) in self.grid.iter().zip(&self.mark) {
+            for (v, marked) in row.iter().zip(mark) {
+                if !marked {
+                    sum += v
                 }
             }
         }
@@ -78,7 +184,7 @@ impl Board {
     }
 }
 
-#[derive(Debug)]
+#[derive(Clone, Debug, PartialEq)]
 struct GameResult {
     sum_of_unmarked_numbers: usize,
     last_number_called: usize,
@@ -90,59 +196,142 @@ impl GameResult {
     }
 }
 
-// plays bingo. takes ownership of params and wrecks them as a side effect
-fn play_to_win(numbers: Vec<usize>, mut boards: Vec<Board>) -> GameResult {
-    for number in numbers {
-        for board in &mut boards {
-            if board.mark(number) {
-                return GameResult {
-                    sum_of_unmarked_numbers: board.sum_unmarked_numbers(),
-                    last_number_called: number,
-                };
-            }
+// a board getting bingo, draw counts from 0
+#[derive(Debug, PartialEq)]
+struct Win {
+    board: usize,
+    draw: usize,
+    result: GameResult,
+}
+
+impl fmt::Display for Win {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(
+            f,
+            "draw {} ({}): board {} wins with score {}",
+            self.draw + 1,
+            self.result.last_number_called,
+            self.board + 1,
+            self.result.score()
+        )
+    }
+}
+
+// every win in the order they happened, plus the boards that never won
+#[derive(Debug)]
+struct GameLog {
+    wins: Vec<Win>,
+    unfinished: Vec<usize>,
+}
+
+impl GameLog {
+    // board numbers in finishing order, boards that never won come last
+    fn ranking(&self) -> Vec<usize> {
+        self.wins
+            .iter()
+            .map(|win| win.board)
+            .chain(self.unfinished.iter().copied())
+            .collect()
+    }
+
+    fn first_win(&self) -> Result<&GameResult, BingoError> {
+        self.wins
+            .first()
+            .map(|win| &win.result)
+            .ok_or(BingoError::NoWinner)
+    }
+
+    // the board that wins last, which only counts once every board has won
+    fn last_win(&self) -> Result<&GameResult, BingoError> {
+        if let Some(&board) = self.unfinished.first() {
+            return Err(BingoError::NeverWins { board });
+        }
+        self.wins
+            .last()
+            .map(|win| &win.result)
+            .ok_or(BingoError::NoWinner)
+    }
+}
+
+impl fmt::Display for GameLog {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for win in &self.wins {
+            writeln!(f, "{}", win)?;
         }
+        for board in &self.unfinished {
+            writeln!(f, "board {} never wins", board + 1)?;
+        }
+        Ok(())
     }
-    panic!("No winning board");
 }
 
-fn play_to_lose(numbers: Vec<usize>, mut boards: Vec<Board>) -> GameResult {
-    let mut boards_in_play = boards.len();
-    for number in numbers {
-        for board in &mut boards {
-            if !board.done && board.mark(number) {
-                boards_in_play -= 1;
-                if boards_in_play == 0 {
-                    return GameResult {
+// plays every number, recording each board as it wins
+fn play(numbers: &[usize], mut boards: Vec<Board>, rules: Rules) -> GameLog {
+    let mut wins = vec![];
+    for (draw, &number) in numbers.iter().enumerate() {
+        for (i, board) in boards.iter_mut().enumerate() {
+            if !board.done && board.mark(number, rules) {
+                wins.push(Win {
+                    board: i,
+                    draw,
+                    result: GameResult {
                         sum_of_unmarked_numbers: board.sum_unmarked_numbers(),
                         last_number_called: number,
-                    };
-                }
+                    },
+                });
             }
         }
     }
-    panic!("Final board didn't win");
+    GameLog {
+        wins,
+        unfinished: (0..boards.len()).filter(|&i| !boards[i].done).collect(),
+    }
+}
+
+fn play_to_win(
+    numbers: &[usize],
+    boards: Vec<Board>,
+    rules: Rules,
+) -> Result<GameResult, BingoError> {
+    play(numbers, boards, rules).first_win().cloned()
+}
+
+fn play_to_lose(
+    numbers: &[usize],
+    boards: Vec<Board>,
+    rules: Rules,
+) -> Result<GameResult, BingoError> {
+    play(numbers, boards, rules).last_win().cloned()
 }
 
-fn parse_input(input: &str) -> (Vec<usize>, Vec<Board>) {
-    let mut numbers = vec![];
+fn parse_number(s: &str) -> Result<usize, BingoError> {
+    s.trim()
+        .parse()
+        .map_err(|_| BingoError::BadNumber(s.to_string()))
+}
+
+fn parse_input(input: &str) -> Result<(Vec<usize>, Vec<Board>), BingoError> {
+    let mut paras = input.split("\n\n");
+    let numbers = paras
+        .next()
+        .unwrap_or_default()
+        .split(',')
+        .map(parse_number)
+        .collect::<Result<Vec<usize>, _>>()?;
     let mut boards = vec![];
-    for (n, para) in input.split("\n\n").enumerate() {
-        if n == 0 {
-            numbers = para.split(',').map(|s| s.parse().unwrap()).collect();
-        } else {
-            boards.push(Board::new(
-                &para
-                    .split('\n')
-                    .map(|row| {
-                        row.split_whitespace()
-                            .map(|s| s.parse().unwrap())
-                            .collect::<Vec<usize>>()
-                    })
-                    .collect::<Vec<Vec<usize>>>(),
-            ));
-        }
+    for (board, para) in paras.enumerate() {
+        let rows = para
+            .lines()
+            .filter(|row| !row.trim().is_empty())
+            .map(|row| {
+                row.split_whitespace()
+                    .map(parse_number)
+                    .collect::<Result<Vec<usize>, _>>()
+            })
+            .collect::<Result<Vec<Vec<usize>>, _>>()?;
+        boards.push(Board::new(&rows, board)?);
     }
-    (numbers, boards)
+    Ok((numbers, boards))
 }
 
 #[test]
@@ -167,7 +356,7 @@ fn test_bingo() {
 22 11 13  6  5
  2  0 12  3  7
 ";
-    let (numbers, boards) = parse_input(test_input);
+    let (numbers, boards) = parse_input(test_input).unwrap();
     assert_eq!(
         numbers,
         vec![
@@ -179,14 +368,71 @@ fn test_bingo() {
     assert_eq!(22, boards[0].grid[0][0]);
     assert_eq!(7, boards[2].grid[4][4]);
 
-    let result = play_to_win(numbers, boards);
+    let result = play_to_win(&numbers, boards, Rules::default()).unwrap();
     assert_eq!(188, result.sum_of_unmarked_numbers);
     assert_eq!(24, result.last_number_called);
     assert_eq!(4512, result.score());
 
-    let (numbers, boards) = parse_input(test_input);
-    let result = play_to_lose(numbers, boards);
+    let (numbers, boards) = parse_input(test_input).unwrap();
+    let result = play_to_lose(&numbers, boards, Rules::default()).unwrap();
     assert_eq!(148, result.sum_of_unmarked_numbers);
     assert_eq!(13, result.last_number_called);
     assert_eq!(1924, result.score());
+
+    let (numbers, boards) = parse_input(test_input).unwrap();
+    let log = play(&numbers, boards, Rules::default());
+    assert_eq!(vec![2, 0, 1], log.ranking());
+    assert_eq!(1924, log.last_win().unwrap().score());
+    assert_eq!(
+        "draw 12 (24): board 3 wins with score 4512",
+        log.wins[0].to_string()
+    );
+    assert_eq!(
+        Win {
+            board: 1,
+            draw: 14,
+            result: GameResult {
+                sum_of_unmarked_numbers: 148,
+                last_number_called: 13
+            }
+        },
+        log.wins[2]
+    );
+
+    // the square board wins on its diagonal.  1 and 5 would be a diagonal of
+    // the 2x3 board, but only square boards have diagonals.
+    let test_input = "1,5,9,3
+
+1 2 3
+4 5 6
+
+9 8
+7 5
+";
+    let (numbers, boards) = parse_input(test_input).unwrap();
+    assert_eq!(3, boards[0].cols());
+    let log = play(&numbers, boards, Rules { diagonals: true });
+    assert_eq!(vec![1, 0], log.ranking());
+    assert_eq!(
+        "draw 3 (9): board 2 wins with score 135\nboard 1 never wins\n",
+        log.to_string()
+    );
+    let (numbers, boards) = parse_input(test_input).unwrap();
+    let log = play(&numbers, boards, Rules::default());
+    assert_eq!(vec![0, 1], log.unfinished);
+    assert_eq!(Err(BingoError::NoWinner), log.first_win());
+    assert_eq!(Err(BingoError::NeverWins { board: 0 }), log.last_win());
+
+    assert_eq!(
+        Some(BingoError::RaggedBoard { board: 1, row: 1 }),
+        parse_input("1,2\n\n1 2\n3 4\n\n1 2\n3\n").err()
+    );
+    assert_eq!(
+        Some(BingoError::EmptyBoard { board: 0 }),
+        parse_input("1,2\n\n\n\n1 2\n").err()
+    );
+    assert_eq!(
+        Some(BingoError::BadNumber("x".to_string())),
+        parse_input("1,x\n\n1 2\n").err()
+    );
 }
@@ -1,35 +1,227 @@
 use crate::Part;
-use std::collections::VecDeque;
+use num_bigint::BigUint;
+use std::fmt;
 
 pub fn run(input: &str, part: Part) -> String {
     let input = parse_input(input);
-    format!(
-        "{}",
-        simulate_population(
-            &input,
-            match part {
-                Part::One => 80,
-                Part::Two => 256,
-            }
-        )
-    )
+    let model = PopulationModel::default();
+    match part {
+        Part::One => format!("{}", simulate_population(&input, 80)),
+        // the same answer as the day by day series, by matrix power
+        Part::Two if crate::verbose() => {
+            let modulus = BigUint::from(1_000_000_007u32);
+            format!(
+                "{}\nafter 10^12 days: {} mod {}",
+                model.population_after(&input, 256),
+                model.population_after_mod(&input, 1_000_000_000_000, &modulus),
+                modulus
+            )
+        }
+        Part::Two => format!("{}", model.population_after(&input, 256)),
+    }
+}
+
+fn simulate_population(fish: &[usize], days: usize) -> BigUint {
+    let mut series = PopulationModel::default().series(fish, days);
+    series.swap_remove(days)
 }
 
-fn simulate_population(fish: &[usize], days: usize) -> usize {
-    // queue containing number of fish at each internal timer (0, 1, ... 8),
-    let mut deq: VecDeque<usize> = VecDeque::from(vec![0; 9]);
-    for &timer in fish.iter() {
-        deq[timer] += 1;
+// A population of fish that all share the same life cycle.  Each fish is in one of a fixed
+// number of states (its internal timer, and how many times it has spawned if they can die), and
+// the number of fish in each state tomorrow is a linear function of the numbers today.  That
+// makes it possible to jump ahead any number of days by raising the transition matrix to a power.
+struct PopulationModel {
+    // days between spawning for an adult (timer resets to cycle - 1)
+    cycle: usize,
+    // extra days before a newborn first spawns
+    newborn_delay: usize,
+    // fish die immediately after spawning this many times
+    max_spawns: Option<usize>,
+}
+
+impl Default for PopulationModel {
+    // the puzzle's lanternfish: timer resets to 6, newborns start at 8, and they never die
+    fn default() -> Self {
+        PopulationModel::new(7, 2, None).unwrap()
     }
+}
+
+#[derive(Debug, PartialEq)]
+enum ModelError {
+    ZeroCycle,  // adults would have to spawn every 0 days
+    ZeroSpawns, // fish would die before they could spawn
+}
+
+impl fmt::Display for ModelError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            ModelError::ZeroCycle => write!(f, "the spawning cycle must be at least 1 day"),
+            ModelError::ZeroSpawns => write!(f, "fish must be able to spawn at least once"),
+        }
+    }
+}
+
+type Matrix = Vec<Vec<BigUint>>;
 
-    for _ in 0..days {
-        // anything at timer 0 resets to timer 6 and spawns a new fish at timer 8
-        let breeding = deq.pop_front().unwrap();
-        deq[6] += breeding;
-        deq.push_back(breeding);
+impl PopulationModel {
+    fn new(
+        cycle: usize,
+        newborn_delay: usize,
+        max_spawns: Option<usize>,
+    ) -> Result<PopulationModel, ModelError> {
+        if cycle == 0 {
+            return Err(ModelError::ZeroCycle);
+        }
+        if max_spawns == Some(0) {
+            return Err(ModelError::ZeroSpawns);
+        }
+        Ok(PopulationModel {
+            cycle,
+            newborn_delay,
+            max_spawns,
+        })
     }
 
-    deq.iter().sum()
+    fn timers(&self) -> usize {
+        self.cycle + self.newborn_delay
+    }
+
+    fn states(&self) -> usize {
+        self.timers() * self.max_spawns.unwrap_or(1)
+    }
+
+    // index of the state for a fish with this timer which has spawned `spawns` times
+    fn state(&self, timer: usize, spawns: usize) -> usize {
+        match self.max_spawns {
+            Some(_) => spawns * self.timers() + timer,
+            None => timer,
+        }
+    }
+
+    // number of fish in each state, for fish given by their initial timers
+    fn initial_state(&self, fish: &[usize]) -> Vec<BigUint> {
+        let mut state = vec![BigUint::default(); self.states()];
+        for &timer in fish {
+            assert!(timer < self.timers(), "Timer {} out of range", timer);
+            state[self.state(timer, 0)] += 1u32;
+        }
+        state
+    }
+
+    // (from, to) for every way a fish can move between states in a day
+    fn transitions(&self) -> Vec<(usize, usize)> {
+        let mut transitions = vec![];
+        for spawns in 0..self.max_spawns.unwrap_or(1) {
+            for timer in 0..self.timers() {
+                let from = self.state(timer, spawns);
+                if timer > 0 {
+                    transitions.push((from, self.state(timer - 1, spawns)));
+                } else {
+                    // spawn a newborn, and reset unless that was this fish's last
+                    transitions.push((from, self.state(self.timers() - 1, 0)));
+                    match self.max_spawns {
+                        Some(max) if spawns + 1 == max => (),
+                        Some(_) => transitions.push((from, self.state(self.cycle - 1, spawns + 1))),
+                        None => transitions.push((from, self.state(self.cycle - 1, 0))),
+                    }
+                }
+            }
+        }
+        transitions
+    }
+
+    fn step(&self, state: &[BigUint]) -> Vec<BigUint> {
+        let mut next = vec![BigUint::default(); state.len()];
+        for (from, to) in self.transitions() {
+            next[to] += &state[from];
+        }
+        next
+    }
+
+    // total population on each day from 0 to `days`
+    fn series(&self, fish: &[usize], days: usize) -> Vec<BigUint> {
+        let mut state = self.initial_state(fish);
+        let mut series = Vec::with_capacity(days + 1);
+        series.push(state.iter().sum());
+        for _ in 0..days {
+            state = self.step(&state);
+            series.push(state.iter().sum());
+        }
+        series
+    }
+
+    // Population after `days`, in O(log days) matrix multiplications.  Bear in mind that the
+    // puzzle's immortal fish grow by about 9% a day, so the exact answer for 10^12 days would
+    // have billions of digits; use population_after_mod() for those.
+    fn population_after(&self, fish: &[usize], days: u64) -> BigUint {
+        self.project(fish, days, None)
+    }
+
+    // population after `days`, modulo `modulus`
+    fn population_after_mod(&self, fish: &[usize], days: u64, modulus: &BigUint) -> BigUint {
+        self.project(fish, days, Some(modulus))
+    }
+
+    fn project(&self, fish: &[usize], days: u64, modulus: Option<&BigUint>) -> BigUint {
+        let n = self.states();
+        let mut matrix: Matrix = vec![vec![BigUint::default(); n]; n];
+        for (from, to) in self.transitions() {
+            matrix[to][from] += 1u32;
+        }
+        let power = matrix_pow(&matrix, days, modulus);
+
+        let state = self.initial_state(fish);
+        let mut total = BigUint::default();
+        for row in power {
+            for (m, count) in row.iter().zip(state.iter()) {
+                total += m * count;
+            }
+        }
+        match modulus {
+            Some(modulus) => total % modulus,
+            None => total,
+        }
+    }
+}
+
+fn matrix_mul(a: &Matrix, b: &Matrix, modulus: Option<&BigUint>) -> Matrix {
+    let n = a.len();
+    let mut c: Matrix = vec![vec![BigUint::default(); n]; n];
+    for i in 0..n {
+        for k in 0..n {
+            if a[i][k] == BigUint::default() {
+                continue;
+            }
+            for j in 0..n {
+                c[i][j] += &a[i][k] * &b[k][j];
+            }
+        }
+        if let Some(modulus) = modulus {
+            for value in c[i].iter_mut() {
+                *value %= modulus;
+            }
+        }
+    }
+    c
+}
+
+// exponentiation by squaring
+fn matrix_pow(matrix: &Matrix, mut exp: u64, modulus: Option<&BigUint>) -> Matrix {
+    let n = matrix.len();
+    let mut result: Matrix = (0..n)
+        .map(|i| (0..n).map(|j| BigUint::from((i == j) as u32)).collect())
+        .collect();
+    let mut base = matrix.clone();
+    while exp > 0 {
+        if exp & 1 == 1 {
+            result = matrix_mul(&result, &base, modulus);
+        }
+        exp >>= 1;
+        if exp > 0 {
+            base = matrix_mul(&base, &base, modulus);
+        }
+    }
+    result
 }
 
 fn parse_input(input: &str) -> Vec<usize> {
@@ -50,23 +242,69 @@ fn test() {
     let input = parse_input(test_input);
     assert_eq!(vec![3, 4, 3, 1, 2], input);
 
-    assert_eq!(5, simulate_population(&input, 0));
-    assert_eq!(5, simulate_population(&input, 1));
-    assert_eq!(6, simulate_population(&input, 2));
-    assert_eq!(7, simulate_population(&input, 3));
-    assert_eq!(9, simulate_population(&input, 4));
-    assert_eq!(10, simulate_population(&input, 5));
-    assert_eq!(10, simulate_population(&input, 6));
-    assert_eq!(10, simulate_population(&input, 7));
-    assert_eq!(10, simulate_population(&input, 8));
-    assert_eq!(11, simulate_population(&input, 9));
-    assert_eq!(12, simulate_population(&input, 10));
-    assert_eq!(15, simulate_population(&input, 11));
-    assert_eq!(17, simulate_population(&input, 12));
-    assert_eq!(19, simulate_population(&input, 13));
-    assert_eq!(20, simulate_population(&input, 14));
-    assert_eq!(20, simulate_population(&input, 15));
-    assert_eq!(21, simulate_population(&input, 16));
-    assert_eq!(22, simulate_population(&input, 17));
-    assert_eq!(26, simulate_population(&input, 18));
+    assert_eq!(BigUint::from(5u32), simulate_population(&input, 0));
+    assert_eq!(BigUint::from(5u32), simulate_population(&input, 1));
+    assert_eq!(BigUint::from(6u32), simulate_population(&input, 2));
+    assert_eq!(BigUint::from(7u32), simulate_population(&input, 3));
+    assert_eq!(BigUint::from(9u32), simulate_population(&input, 4));
+    assert_eq!(BigUint::from(10u32), simulate_population(&input, 5));
+    assert_eq!(BigUint::from(10u32), simulate_population(&input, 6));
+    assert_eq!(BigUint::from(10u32), simulate_population(&input, 7));
+    assert_eq!(BigUint::from(10u32), simulate_population(&input, 8));
+    assert_eq!(BigUint::from(11u32), simulate_population(&input, 9));
+    assert_eq!(BigUint::from(12u32), simulate_population(&input, 10));
+    assert_eq!(BigUint::from(15u32), simulate_population(&input, 11));
+    assert_eq!(BigUint::from(17u32), simulate_population(&input, 12));
+    assert_eq!(BigUint::from(19u32), simulate_population(&input, 13));
+    assert_eq!(BigUint::from(20u32), simulate_population(&input, 14));
+    assert_eq!(BigUint::from(20u32), simulate_population(&input, 15));
+    assert_eq!(BigUint::from(21u32), simulate_population(&input, 16));
+    assert_eq!(BigUint::from(22u32), simulate_population(&input, 17));
+    assert_eq!(BigUint::from(26u32), simulate_population(&input, 18));
+
+    let model = PopulationModel::default();
+    assert_eq!(BigUint::from(5934u32), model.population_after(&input, 80));
+    assert_eq!(
+        BigUint::from(26984457539u64),
+        model.population_after(&input, 256)
+    );
+    let series = model.series(&input, 256);
+    for days in [0, 1, 18, 80, 200, 256] {
+        assert_eq!(series[days], model.population_after(&input, days as u64));
+    }
+    let modulus = BigUint::from(1_000_000_007u32);
+    assert_eq!(
+        series[256].clone() % &modulus,
+        model.population_after_mod(&input, 256, &modulus)
+    );
+    // 10^12 days is only practical modulo something
+    assert!(model.population_after_mod(&input, 1_000_000_000_000, &modulus) < modulus);
+
+    // fish which die after spawning once just replace themselves
+    let model = PopulationModel::new(7, 2, Some(1)).unwrap();
+    assert_eq!(vec![BigUint::from(5u32); 41], model.series(&input, 40));
+    assert_eq!(
+        BigUint::from(5u32),
+        model.population_after(&input, 1_000_000_000_000)
+    );
+
+    // fish which spawn twice: the population can never shrink, and matches the day by day count
+    let model = PopulationModel::new(3, 1, Some(2)).unwrap();
+    let series = model.series(&[0], 20);
+    assert_eq!(BigUint::from(1u32), series[0]);
+    assert_eq!(BigUint::from(2u32), series[1]);
+    assert!(series.windows(2).all(|w| w[0] <= w[1]));
+    assert_eq!(series[20], model.population_after(&[0], 20));
+
+    assert_eq!(
+        Some(ModelError::ZeroCycle),
+        PopulationModel::new(0, 2, None).err()
+    );
+    assert_eq!(
+        "fish must be able to spawn at least once",
+        PopulationModel::new(7, 2, Some(0))
+            .err()
+            .unwrap()
+            .to_string()
+    );
 }
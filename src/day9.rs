@@ -1,14 +1,60 @@
+use crate::animate::paint;
+use crate::raster::{Raster, Rasterize, BLACK};
 use crate::Part;
-use std::collections::HashSet;
 
 pub fn run(input: &str, part: Part) -> String {
     let grid = Grid::new(input);
     match part {
         Part::One => format!("{}", grid.part1()),
+        Part::Two if crate::verbose() => {
+            let basins = grid.basins();
+            format!("{}\n{}", grid.part2(), basins.render(&grid))
+        }
         Part::Two => format!("{}", grid.part2()),
     }
 }
 
+#[derive(Debug, PartialEq)]
+struct Basin {
+    area: usize,
+    // cells at the basin's lowest height, more than one when the bottom of
+    // the basin is a plateau
+    low_points: Vec<(i32, i32)>,
+}
+
+// every cell that isn't a 9 belongs to exactly one basin
+#[derive(Debug)]
+struct Basins {
+    labels: Vec<Vec<Option<usize>>>, // basin id for each cell, by [y][x]
+    basins: Vec<Basin>,              // indexed by basin id
+}
+
+impl Basins {
+    fn label(&self, x: i32, y: i32) -> Option<usize> {
+        self.labels[y as usize][x as usize]
+    }
+
+    // the height map with each basin in its own colour and the ridges dimmed
+    fn render(&self, grid: &Grid) -> String {
+        const COLOURS: [&str; 6] = ["31", "32", "33", "34", "35", "36"];
+        let mut s = String::new();
+        for y in 0..grid.height {
+            for x in 0..grid.width {
+                let height = grid.get(x, y).to_string();
+                s += &match self.label(x, y) {
+                    Some(id) if self.basins[id].low_points.contains(&(x, y)) => {
+                        paint(&height, &format!("1;{}", COLOURS[id % COLOURS.len()]))
+                    }
+                    Some(id) => paint(&height, COLOURS[id % COLOURS.len()]),
+                    None => paint(&height, "90"),
+                };
+            }
+            s += "\n";
+        }
+        s
+    }
+}
+
 struct Grid {
     data: Vec<Vec<u32>>,
     width: i32,
@@ -62,29 +108,73 @@ impl Grid {
             .sum()
     }
 
-    // recursively measure the size of a basin, including this point
-    fn basin_size(&self, from: (i32, i32), seen: &mut HashSet<(i32, i32)>) -> usize {
-        seen.insert(from);
-        let mut size = 1;
-        let this = self.get(from.0, from.1);
-        for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
-            let to = (from.0 + dx, from.1 + dy);
-            let that = self.get(to.0, to.1);
-            if that >= this && that != 9 && !seen.contains(&to) {
-                size += self.basin_size(to, seen);
+    // label the basins with an iterative flood fill, basins are numbered in
+    // the order their first cell is found scanning row by row
+    fn basins(&self) -> Basins {
+        let mut labels = vec![vec![None; self.width as usize]; self.height as usize];
+        let mut basins = vec![];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if self.get(x, y) == 9 || labels[y as usize][x as usize].is_some() {
+                    continue;
+                }
+                let id = basins.len();
+                let mut area = 0;
+                let mut lowest = 9;
+                let mut low_points = vec![];
+                labels[y as usize][x as usize] = Some(id);
+                let mut stack = vec![(x, y)];
+                while let Some((x, y)) = stack.pop() {
+                    area += 1;
+                    let height = self.get(x, y);
+                    if height < lowest {
+                        lowest = height;
+                        low_points.clear();
+                    }
+                    if height == lowest {
+                        low_points.push((x, y));
+                    }
+                    for (dx, dy) in [(-1, 0), (1, 0), (0, -1), (0, 1)] {
+                        let (nx, ny) = (x + dx, y + dy);
+                        // get gives 9 outside the grid, so this stays in bounds
+                        if self.get(nx, ny) != 9 && labels[ny as usize][nx as usize].is_none() {
+                            labels[ny as usize][nx as usize] = Some(id);
+                            stack.push((nx, ny));
+                        }
+                    }
+                }
+                low_points.sort_unstable();
+                basins.push(Basin { area, low_points });
             }
         }
-        size
+        Basins { labels, basins }
     }
 
     fn part2(&self) -> usize {
-        let mut seen: HashSet<(i32, i32)> = HashSet::new();
-        let mut basins: Vec<usize> = vec![];
-        for start in self.lowpoints() {
-            basins.push(self.basin_size(start, &mut seen));
+        let mut areas: Vec<usize> = self.basins().basins.iter().map(|b| b.area).collect();
+        areas.sort_unstable();
+        areas.iter().rev().take(3).product()
+    }
+}
+
+pub fn frames(input: &str) -> Vec<Raster> {
+    vec![Grid::new(input).rasterize()]
+}
+
+// low ground is bright blue, ridges of 9 are black and low points are red
+impl Rasterize for Grid {
+    fn rasterize(&self) -> Raster {
+        let mut raster = Raster::new(self.width as usize, self.height as usize, BLACK);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let level = (9 - self.get(x, y)) as u8 * 28;
+                raster.set(x as usize, y as usize, [level / 4, level / 2, level]);
+            }
+        }
+        for (x, y) in self.lowpoints() {
+            raster.set(x as usize, y as usize, [255, 40, 40]);
         }
-        basins.sort_unstable();
-        basins.iter().rev().take(3).product()
+        raster
     }
 }
 
@@ -97,7 +187,37 @@ fn test() {
 8767896789
 9899965678
 ";
-    let map = Grid::new(&test_input);
+    let map = Grid::new(test_input);
     assert_eq!(15, map.part1());
     assert_eq!(1134, map.part2());
+
+    let basins = map.basins();
+    assert_eq!(4, basins.basins.len());
+    assert_eq!(
+        Basin {
+            area: 3,
+            low_points: vec![(1, 0)]
+        },
+        basins.basins[0]
+    );
+    assert_eq!(
+        vec![3, 9, 14, 9],
+        basins.basins.iter().map(|b| b.area).collect::<Vec<_>>()
+    );
+    assert_eq!(Some(1), basins.label(9, 0));
+    assert_eq!(None, basins.label(2, 0));
+    assert!(basins.render(&map).starts_with(&paint("2", "31")));
+
+    // a basin with a flat bottom has several low points, none of which count
+    // for part 1
+    let map = Grid::new("9999\n9119\n9239\n9999\n");
+    assert_eq!(0, map.part1());
+    let basins = map.basins();
+    assert_eq!(
+        vec![Basin {
+            area: 4,
+            low_points: vec![(1, 1), (2, 1)]
+        }],
+        basins.basins
+    );
 }
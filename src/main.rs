@@ -3,8 +3,10 @@ use std::fs::File;
 use std::io;
 use std::io::Read;
 use std::slice::Iter;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::time::Instant;
 
+mod animate;
 mod day1;
 mod day10;
 mod day11;
@@ -30,6 +32,9 @@ mod day6;
 mod day7;
 mod day8;
 mod day9;
+mod raster;
+#[cfg(test)]
+mod rng;
 
 #[derive(Clone, Copy)]
 pub enum Part {
@@ -53,10 +58,28 @@ impl fmt::Display for Part {
     }
 }
 
+// set by --verbose, days that have extra detail to show check this
+static VERBOSE: AtomicBool = AtomicBool::new(false);
+
+pub fn verbose() -> bool {
+    VERBOSE.load(Ordering::Relaxed)
+}
+
 fn main() {
-    let args: Vec<_> = std::env::args().collect();
+    let mut args: Vec<_> = std::env::args().collect();
+    if let Some(i) = args
+        .iter()
+        .position(|arg| arg == "--verbose" || arg == "-v")
+    {
+        VERBOSE.store(true, Ordering::Relaxed);
+        args.remove(i);
+    }
 
-    if args.len() == 2 {
+    if args.len() >= 2 && args[1] == "animate" {
+        animate::run(&args[2..]);
+    } else if args.len() >= 2 && args[1] == "export" {
+        raster::run(&args[2..]);
+    } else if args.len() == 2 {
         let day = args[1].parse().unwrap();
         run(day);
     } else {
@@ -0,0 +1,243 @@
+use crate::{day13, day19, day20, day25, day9};
+use std::collections::HashMap;
+use std::fs;
+use std::fs::File;
+use std::io;
+use std::io::{BufWriter, Write};
+
+pub type Colour = [u8; 3];
+
+pub const BLACK: Colour = [0, 0, 0];
+pub const WHITE: Colour = [255, 255, 255];
+
+// rgb picture, one colour per puzzle cell
+#[derive(Clone, Debug, PartialEq)]
+pub struct Raster {
+    width: usize,
+    height: usize,
+    pixels: Vec<Colour>,
+}
+
+// anything that can be drawn as a raster
+pub trait Rasterize {
+    fn rasterize(&self) -> Raster;
+}
+
+impl Raster {
+    pub fn new(width: usize, height: usize, background: Colour) -> Raster {
+        Raster {
+            width,
+            height,
+            pixels: vec![background; width * height],
+        }
+    }
+
+    pub fn set(&mut self, x: usize, y: usize, colour: Colour) {
+        self.pixels[y * self.width + x] = colour;
+    }
+
+    fn get(&self, x: usize, y: usize) -> Colour {
+        self.pixels[y * self.width + x]
+    }
+
+    // each pixel becomes a square of scale x scale pixels
+    fn scaled(&self, scale: usize) -> Raster {
+        let mut big = Raster::new(self.width * scale, self.height * scale, BLACK);
+        for y in 0..big.height {
+            for x in 0..big.width {
+                big.set(x, y, self.get(x / scale, y / scale));
+            }
+        }
+        big
+    }
+
+    // centre this raster on a larger canvas
+    fn padded(&self, width: usize, height: usize, background: Colour) -> Raster {
+        let mut canvas = Raster::new(width, height, background);
+        let left = (width - self.width) / 2;
+        let top = (height - self.height) / 2;
+        for y in 0..self.height {
+            for x in 0..self.width {
+                canvas.set(left + x, top + y, self.get(x, y));
+            }
+        }
+        canvas
+    }
+
+    fn rgb_bytes(&self) -> Vec<u8> {
+        self.pixels.iter().flatten().copied().collect()
+    }
+
+    // binary portable pixmap
+    fn ppm(&self) -> Vec<u8> {
+        let mut bytes = format!("P6\n{} {}\n255\n", self.width, self.height).into_bytes();
+        bytes.extend(self.rgb_bytes());
+        bytes
+    }
+
+    fn write_ppm(&self, w: impl Write) -> io::Result<()> {
+        let mut w = w;
+        w.write_all(&self.ppm())
+    }
+
+    fn write_png(&self, w: impl Write) -> io::Result<()> {
+        let mut encoder = png::Encoder::new(w, self.width as u32, self.height as u32);
+        encoder.set_color(png::ColorType::Rgb);
+        encoder.set_depth(png::BitDepth::Eight);
+        let mut writer = encoder.write_header()?;
+        writer.write_image_data(&self.rgb_bytes())?;
+        Ok(())
+    }
+}
+
+// animated gif of all frames, scaled and centred on a canvas big enough for
+// the largest.  delay is in hundredths of a second.  puzzle pictures only use
+// a handful of colours, so more than 256 is an error rather than quantised.
+fn write_gif(w: impl Write, frames: &[Raster], scale: usize, delay: u16) -> io::Result<()> {
+    let invalid = |msg: &str| io::Error::new(io::ErrorKind::InvalidInput, msg);
+    if frames.is_empty() {
+        return Err(invalid("no frames to write"));
+    }
+    let width = frames.iter().map(|f| f.width).max().unwrap() * scale;
+    let height = frames.iter().map(|f| f.height).max().unwrap() * scale;
+    if width > u16::MAX as usize || height > u16::MAX as usize {
+        return Err(invalid("image too large for a gif"));
+    }
+
+    let mut palette: HashMap<Colour, u8> = HashMap::new();
+    let mut palette_bytes = vec![];
+    for colour in frames.iter().flat_map(|f| &f.pixels) {
+        if !palette.contains_key(colour) {
+            if palette.len() == 256 {
+                return Err(invalid("more than 256 colours"));
+            }
+            palette.insert(*colour, palette.len() as u8);
+            palette_bytes.extend(colour);
+        }
+    }
+
+    let mut encoder = gif::Encoder::new(w, width as u16, height as u16, &palette_bytes)
+        .map_err(io::Error::other)?;
+    encoder
+        .set_repeat(gif::Repeat::Infinite)
+        .map_err(io::Error::other)?;
+    for raster in frames {
+        let canvas = raster.scaled(scale).padded(width, height, BLACK);
+        let mut frame = gif::Frame {
+            width: width as u16,
+            height: height as u16,
+            delay,
+            buffer: canvas.pixels.iter().map(|c| palette[c]).collect(),
+            ..Default::default()
+        };
+        frame.make_lzw_pre_encoded();
+        encoder
+            .write_lzw_pre_encoded_frame(&frame)
+            .map_err(io::Error::other)?;
+    }
+    Ok(())
+}
+
+const USAGE: &str = "\
+usage: adventofcode-2021 export <day> <file.ppm|file.png|file.gif> [--scale N] [--delay CS]
+       adventofcode-2021 export 19 <file.json|file.ply|file.xyz>
+days: 9, 13, 20, 25
+ppm and png show the final state, gif shows every step
+day 19 writes the beacon map, or the beacons and scanners as a point cloud";
+
+fn frames(day: i32, input: &str) -> Result<Vec<Raster>, String> {
+    match day {
+        9 => Ok(day9::frames(input)),
+        13 => day13::frames(input),
+        20 => Ok(day20::frames(input)),
+        25 => Ok(day25::frames(input)),
+        _ => Err(format!("day {} has no pictures", day)),
+    }
+}
+
+pub fn run(args: &[String]) {
+    if let Err(e) = export(args) {
+        println!("{}\n{}", e, USAGE);
+    }
+}
+
+fn export(args: &[String]) -> Result<(), String> {
+    let mut positional = vec![];
+    let mut scale = 4;
+    let mut delay = 10;
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut number = || -> Result<usize, String> {
+            args.next()
+                .and_then(|value| value.parse().ok())
+                .ok_or(format!("{} needs a number", arg))
+        };
+        match arg.as_str() {
+            "--scale" => scale = number()?.max(1),
+            "--delay" => delay = number()?.min(u16::MAX as usize) as u16,
+            _ => positional.push(arg),
+        }
+    }
+    let (day, path) = match positional[..] {
+        [day, path] => (day.parse().map_err(|_| "day must be a number")?, path),
+        _ => return Err("expected a day and a file name".to_string()),
+    };
+
+    let filename = format!("input/day{}.txt", day);
+    let input = crate::read_file(&filename).map_err(|e| format!("{}: {}", filename, e))?;
+    let extension = path.rsplit('.').next().unwrap();
+    if day == 19 {
+        let text = day19::export(&input, extension).map_err(|e| format!("{}: {}", path, e))?;
+        fs::write(path, text).map_err(|e| format!("{}: {}", path, e))?;
+        println!("wrote {}", path);
+        return Ok(());
+    }
+    let frames = frames(day, &input)?;
+
+    if !["ppm", "png", "gif"].contains(&extension) {
+        return Err(format!("{}: unknown image type", path));
+    }
+    let file = BufWriter::new(File::create(path).map_err(|e| format!("{}: {}", path, e))?);
+    let last = frames.last().unwrap();
+    match extension {
+        "ppm" => last.scaled(scale).write_ppm(file),
+        "png" => last.scaled(scale).write_png(file),
+        _ => write_gif(file, &frames, scale, delay),
+    }
+    .map_err(|e| format!("{}: {}", path, e))?;
+    println!("wrote {} ({} frames)", path, frames.len());
+    Ok(())
+}
+
+#[test]
+fn test() {
+    let mut raster = Raster::new(2, 1, BLACK);
+    raster.set(1, 0, [1, 2, 3]);
+    assert_eq!(b"P6\n2 1\n255\n\0\0\0\x01\x02\x03".to_vec(), raster.ppm());
+
+    let big = raster.scaled(2);
+    assert_eq!((4, 2), (big.width, big.height));
+    assert_eq!([1, 2, 3], big.get(3, 1));
+    assert_eq!(BLACK, big.get(1, 1));
+
+    let canvas = raster.padded(4, 3, WHITE);
+    assert_eq!(BLACK, canvas.get(1, 1));
+    assert_eq!([1, 2, 3], canvas.get(2, 1));
+    assert_eq!(WHITE, canvas.get(0, 0));
+
+    let mut png = vec![];
+    raster.write_png(&mut png).unwrap();
+    assert_eq!(b"\x89PNG", &png[..4]);
+
+    let mut gif = vec![];
+    write_gif(&mut gif, &[raster.clone(), big], 2, 5).unwrap();
+    assert_eq!(b"GIF89a", &gif[..6]);
+    assert_eq!([8, 0, 4, 0], gif[6..10]); // canvas fits the larger frame
+
+    let mut many = Raster::new(257, 1, BLACK);
+    for x in 0..257 {
+        many.set(x, 0, [x as u8, (x / 256) as u8, 0]);
+    }
+    assert!(write_gif(&mut vec![], &[many], 1, 5).is_err());
+    assert!(write_gif(&mut vec![], &[], 1, 5).is_err());
+}
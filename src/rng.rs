@@ -0,0 +1,43 @@
+// xorshift64, for generating repeatable random puzzle inputs in tests and benchmarks
+pub struct Rng(u64);
+
+impl Rng {
+    // a zero state would only ever produce zeros
+    pub fn new(seed: u64) -> Rng {
+        Rng(seed.max(1))
+    }
+
+    pub fn next(&mut self) -> u64 {
+        self.0 ^= self.0 << 13;
+        self.0 ^= self.0 >> 7;
+        self.0 ^= self.0 << 17;
+        self.0
+    }
+
+    // in 0..n
+    pub fn below(&mut self, n: usize) -> usize {
+        (self.next() % n as u64) as usize
+    }
+
+    // in min..=max
+    pub fn range(&mut self, min: i32, max: i32) -> i32 {
+        min + (self.next() % (max - min + 1) as u64) as i32
+    }
+}
+
+#[test]
+fn test() {
+    let mut rng = Rng::new(0);
+    let values: Vec<u64> = (0..100).map(|_| rng.next()).collect();
+    assert!(values.iter().all(|&v| v != 0));
+    assert_eq!(values, {
+        let mut rng = Rng::new(1);
+        (0..100).map(|_| rng.next()).collect::<Vec<_>>()
+    });
+
+    let mut rng = Rng::new(2021);
+    for _ in 0..1000 {
+        assert!(rng.below(7) < 7);
+        assert!((-3..=3).contains(&rng.range(-3, 3)));
+    }
+}